@@ -1,12 +1,55 @@
-use ab_glyph::{Font, ScaleFont};
+use ab_glyph::{Font, FontArc, OutlinedGlyph, PxScaleFont, Rect, ScaleFont};
+use core::num::NonZeroUsize;
 use std::collections::{HashMap, HashSet};
+use crate::cluster::Cluster;
+use crate::error::{Error, Result};
+use crate::grid::{Attributes, GlyphKey, Grid, Span, Underline};
 use crate::sequence::GridSequence;
+use crate::shape;
+
+struct Slot {
+	key: GlyphKey,
+	last_used: u64
+}
+
+// a horizontal line, its position is in pixels above the baseline
+#[derive(Copy, Clone)]
+struct LineMetrics {
+	position: f32,
+	thickness: f32
+}
+
+struct Decorations {
+	underline: LineMetrics,
+	strikeout: LineMetrics
+}
 
 pub(crate) struct Atlas {
 	pub buffer: Vec<u8>,
-	pub lut: HashMap<char, u32>,
+	pub lut: HashMap<GlyphKey, u32>,
 	pub font_width: u32,
-	pub font_height: u32
+	pub font_height: u32,
+	/// glyphs beyond this are evicted least recently used first, `None` grows forever
+	pub max_glyphs: Option<NonZeroUsize>,
+	/// how thick bar, underline and hollow cursors are drawn
+	pub cursor_thickness: u32,
+	font: PxScaleFont<FontArc>,
+	decorations: Decorations,
+	slots: Vec<Slot>,
+	// slots rasterised since the last call to `take_dirty`
+	dirty: Vec<u32>,
+	// bumped for every prepared grid
+	clock: u64
+}
+
+// a grid can only be rendered if it's the size of the sequence
+pub(crate) fn check_grid(sequence: &GridSequence, grid: &Grid) -> Result<()> {
+	let (width, height) = sequence.get_dimensions();
+	if grid.width() != width.get() || grid.height() != height.get() {
+		return Err(Error::MismatchedGrid);
+	}
+
+	Ok(())
 }
 
 // partially aesthetic, partially a `wgpu` hack for buffer alignment
@@ -20,65 +63,374 @@ fn round_up_aligned(n: u32) -> u32 {
 	(ALIGN * (n / ALIGN)) + ALIGN
 }
 
-// upper bound of size for the biggest glyph
-fn font_size<F: Font, SF: ScaleFont<F>>(font: &SF, glyph_set: &HashSet<char>) -> (u32, u32) {
-	let mut font_width = f32::MIN;
-	let mut font_height = f32::MIN;
+// a cluster's glyphs laid out one after another, so that zero width marks land on their base
+fn outline_cluster(font: &PxScaleFont<FontArc>, cluster: Cluster) -> Vec<OutlinedGlyph> {
+	let mut glyphs: Vec<_> = cluster.chars()
+		.map(|c| ttf_parser::GlyphId(font.glyph_id(c).0))
+		.collect();
 
-	for glyph_char in glyph_set {
-		let glyph = font.scaled_glyph(*glyph_char);
-		if let Some(outline) = font.outline_glyph(glyph) {
-			font_width = font_width.max(outline.px_bounds().width());
-			font_height = font_height.max(outline.px_bounds().height() - font.descent());
+	if glyphs.len() > 1 {
+		if let Ok(face) = ttf_parser::Face::parse(font.font.font_data(), 0) {
+			shape::ligate(&face, &mut glyphs);
 		}
+
+		// joiners and variation selectors the font doesn't know about have nothing to draw
+		glyphs.retain(|glyph| glyph.0 != 0);
 	}
 
-	assert!(font_width != f32::MIN && font_height != f32::MIN, "font has no glyphs");
+	let mut caret = 0.0;
+	glyphs.into_iter()
+		.filter_map(|glyph| {
+			let glyph = ab_glyph::GlyphId(glyph.0);
+			let position = ab_glyph::point(caret, 0.0);
+			caret += font.h_advance(glyph);
+
+			font.outline_glyph(glyph.with_scale_and_position(font.scale(), position))
+		})
+		.collect()
+}
+
+// the smallest rect containing every outline
+fn union_bounds(outlines: &[OutlinedGlyph]) -> Option<Rect> {
+	outlines.iter()
+		.map(OutlinedGlyph::px_bounds)
+		.reduce(|a, b| Rect {
+			min: ab_glyph::point(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+			max: ab_glyph::point(a.max.x.max(b.max.x), a.max.y.max(b.max.y))
+		})
+}
+
+// the font's advance and line height, grown to fit the biggest glyph,
+// wide glyphs get two cells' worth of width
+fn font_size(font: &PxScaleFont<FontArc>, glyph_set: &HashSet<Cluster>) -> (u32, u32) {
+	// the advance of `0`, like css' `ch` unit
+	let mut font_width = font.h_advance(font.glyph_id('0'));
+	let mut font_height = font.ascent() - font.descent();
+
+	for cluster in glyph_set {
+		// widths come from the base alone, a sequence the font can't ligate would blow up every cell
+		let glyph = font.scaled_glyph(cluster.base());
+		if let Some(outline) = font.outline_glyph(glyph) {
+			let columns = cluster.width() as f32;
+			font_width = font_width.max(outline.px_bounds().width() / columns);
+		}
+
+		if let Some(bounds) = union_bounds(&outline_cluster(font, *cluster)) {
+			font_height = font_height.max(bounds.height() - font.descent());
+		}
+	}
 
 	(round_up_aligned(font_width as u32), round_up_aligned(font_height as u32))
 }
 
-pub(crate) fn populate_atlas<F: Font>(font: F, sequence: &GridSequence) -> Atlas {
-	let font = font.as_scaled(sequence.resolve_px_scale(&font));
+// the font's own underline and strikeout metrics, or guesses based on its ascent and descent
+fn decorations(font: &PxScaleFont<FontArc>) -> Decorations {
+	let v_scale = font.v_scale_factor();
+	let fallback_thickness = (font.height() / 16.0).max(1.0);
 
-	let (font_width, font_height) = font_size(&font, sequence.glyph_set());
+	let face = ttf_parser::Face::parse(font.font.font_data(), 0).ok();
+	let to_px = |metrics: ttf_parser::LineMetrics| LineMetrics {
+		position: metrics.position as f32 * v_scale,
+		thickness: metrics.thickness as f32 * v_scale
+	};
 
-	let mut atlas_img = image::GrayImage::new(font_width, sequence.glyph_set().len() as u32 * font_height);
-	let mut cursor_y = 0;
-	let mut lut = HashMap::new();
-
-	for (i, glyph_char) in sequence.glyph_set().iter().enumerate() {
-		let glyph = font.scaled_glyph(*glyph_char);
-		let Some(outline) = font.outline_glyph(glyph) else {
-			// if no outline is present just skip drawing
-			lut.insert(*glyph_char, i as u32);
-			cursor_y += font_height;
-			continue;
-		};
+	Decorations {
+		underline: face.as_ref()
+			.and_then(ttf_parser::Face::underline_metrics)
+			.map(to_px)
+			.unwrap_or(LineMetrics {
+				position: font.descent() / 2.0,
+				thickness: fallback_thickness
+			}),
+		strikeout: face.as_ref()
+			.and_then(ttf_parser::Face::strikeout_metrics)
+			.map(to_px)
+			.unwrap_or(LineMetrics {
+				position: font.ascent() * 0.3,
+				thickness: fallback_thickness
+			})
+	}
+}
 
-		lut.insert(*glyph_char, i as u32);
+// the row of the baseline within a slot
+#[inline]
+fn baseline(font: &PxScaleFont<FontArc>, font_height: u32) -> f32 {
+	font_height as f32 + font.descent()
+}
 
-		let px_bounds = outline.px_bounds();
-		let glyph_width = px_bounds.width() as u32;
+// draws a cluster into `slot`, which is `font_height` rows of `font_width` pixels
+fn draw_glyph(font: &PxScaleFont<FontArc>, cluster: Cluster, font_width: u32, font_height: u32, slot: &mut [u8]) {
+	let outlines = outline_cluster(font, cluster);
+	let Some(bounds) = union_bounds(&outlines) else {
+		// if no outline is present just skip drawing
+		return;
+	};
 
-		let baseline_diff = font_height - (-px_bounds.min.y - font.descent()) as u32;
+	let glyph_width = bounds.width() as u32;
+
+	let baseline_diff = font_height.saturating_sub((-bounds.min.y - font.descent()) as u32);
+	let x_offset = font_width.saturating_sub(glyph_width) / 2;
+
+	for outline in &outlines {
+		let px_bounds = outline.px_bounds();
+		let dx = (px_bounds.min.x - bounds.min.x) as u32 + x_offset;
+		let dy = (px_bounds.min.y - bounds.min.y) as u32 + baseline_diff;
 
 		outline.draw(|x, y, c| {
-			let luma = (c * u8::MAX as f32) as u8;
-			atlas_img.put_pixel(
-				x + ((font_width - glyph_width) / 2),
-				cursor_y + y + baseline_diff,
-				image::Luma([luma])
-			);
+			let (x, y) = (x + dx, y + dy);
+
+			// glyphs reaching below the font's descent get clipped to their own slot
+			if x >= font_width || y >= font_height {
+				return;
+			}
+
+			// overlapping glyphs keep whichever covers the pixel most
+			let px = &mut slot[((y * font_width) + x) as usize];
+			*px = (*px).max((c * u8::MAX as f32) as u8);
 		});
+	}
+}
+
+// slants every row of `slot` right in proportion to its height above the baseline
+fn shear(slot: &mut [u8], font_width: u32, baseline: f32) {
+	const SLANT: f32 = 0.2;
+
+	for (y, row) in slot.chunks_exact_mut(font_width as usize).enumerate() {
+		let shift = (baseline - y as f32 - 0.5) * SLANT;
+		let whole = shift.floor() as isize;
+		let frac = shift - shift.floor();
+
+		let src = row.to_vec();
+		let at = |x: isize| usize::try_from(x).ok()
+			.and_then(|x| src.get(x))
+			.map_or(0.0, |&c| c as f32);
+
+		for (x, px) in row.iter_mut().enumerate() {
+			let x = x as isize - whole;
+			*px = ((at(x) * (1.0 - frac)) + (at(x - 1) * frac)).round() as u8;
+		}
+	}
+}
+
+// thickens every stroke of `slot` to the right
+fn embolden(slot: &mut [u8], font_width: u32, font_height: u32) {
+	let strength = (font_height / 16).max(1) as usize;
+
+	for row in slot.chunks_exact_mut(font_width as usize) {
+		for x in (0..row.len()).rev() {
+			let start = x.saturating_sub(strength);
+			row[x] = row[start..=x].iter().copied().max().unwrap_or(0);
+		}
+	}
+}
+
+// fills `thickness` rows from `top` in every column where `on` holds, clipped to the slot
+fn draw_line(slot: &mut [u8], font_width: u32, top: i32, thickness: u32, on: impl Fn(u32) -> bool) {
+	let font_height = (slot.len() / font_width as usize) as i32;
+
+	for y in top.max(0)..(top + thickness as i32).min(font_height) {
+		for x in (0..font_width).filter(|&x| on(x)) {
+			slot[((y as u32 * font_width) + x) as usize] = u8::MAX;
+		}
+	}
+}
+
+// lines span the whole cell, so that they join up with their neighbours
+fn draw_decorations(
+	decorations: &Decorations,
+	font: &PxScaleFont<FontArc>,
+	attrs: Attributes,
+	font_width: u32,
+	font_height: u32,
+	slot: &mut [u8]
+) {
+	let baseline = baseline(font, font_height);
+	let line = |metrics: LineMetrics| {
+		let top = (baseline - metrics.position).round() as i32;
+		(top, metrics.thickness.round().max(1.0) as u32)
+	};
 
-		cursor_y += font_height;
+	let (top, thickness) = line(decorations.underline);
+	match attrs.underline() {
+		Underline::None => (),
+		Underline::Single => draw_line(slot, font_width, top, thickness, |_| true),
+		Underline::Double => {
+			// the second line mustn't fall off the bottom of the cell
+			let top = top.min(font_height as i32 - (3 * thickness) as i32);
+			draw_line(slot, font_width, top, thickness, |_| true);
+			draw_line(slot, font_width, top + (2 * thickness) as i32, thickness, |_| true);
+		},
+		Underline::Curly => {
+			// one period of a sine wave per cell
+			let amplitude = thickness as i32;
+			let top = top.min(font_height as i32 - thickness as i32 - amplitude).max(amplitude);
+
+			for x in 0..font_width {
+				let phase = x as f32 / font_width as f32 * core::f32::consts::TAU;
+				let y = top + (phase.sin() * amplitude as f32).round() as i32;
+				draw_line(slot, font_width, y, thickness, |col| col == x);
+			}
+		},
+		Underline::Dotted => draw_line(slot, font_width, top, thickness, |x| x % (2 * thickness) < thickness),
+		Underline::Dashed => {
+			// two dashes per cell
+			let period = (font_width / 2).max(2);
+			draw_line(slot, font_width, top, thickness, |x| x % period < (period * 3) / 4);
+		}
+	}
+
+	if attrs.contains(Attributes::STRIKETHROUGH) {
+		let (top, thickness) = line(decorations.strikeout);
+		draw_line(slot, font_width, top, thickness, |_| true);
 	}
 
-	Atlas {
-		buffer: atlas_img.into_vec(),
-		lut,
+	if attrs.contains(Attributes::OVERLINE) {
+		let top = (baseline - font.ascent()).round() as i32;
+		draw_line(slot, font_width, top, thickness, |_| true);
+	}
+}
+
+pub(crate) fn populate_atlas<F: Font + Send + Sync + 'static>(font: F, sequence: &GridSequence) -> Result<Atlas> {
+	let scale = sequence.resolve_px_scale(&font)?;
+	let font = FontArc::new(font).into_scaled(scale);
+
+	let (font_width, font_height) = font_size(&font, sequence.glyph_set());
+	let decorations = decorations(&font);
+
+	let mut atlas = Atlas {
+		buffer: Vec::new(),
+		lut: HashMap::new(),
 		font_width,
-		font_height
+		font_height,
+		max_glyphs: None,
+		// as thick as an underline
+		cursor_thickness: decorations.underline.thickness.round().max(1.0) as u32,
+		decorations,
+		font,
+		slots: Vec::new(),
+		dirty: Vec::new(),
+		clock: 0
+	};
+
+	for key in sequence.glyph_keys() {
+		atlas.push(*key);
+	}
+
+	atlas.dirty.clear();
+
+	Ok(atlas)
+}
+
+impl Atlas {
+	#[inline]
+	pub fn slot_len(&self) -> usize {
+		self.font_width as usize * self.font_height as usize
+	}
+
+	#[cfg(feature = "gpu")]
+	#[inline]
+	pub fn glyph_count(&self) -> usize {
+		self.slots.len()
+	}
+
+	/// rasterises any glyphs in `grid` that aren't in the atlas yet,
+	/// evicting the least recently used ones if `max_glyphs` has been reached
+	pub fn prepare(&mut self, grid: &Grid) -> Result<()> {
+		self.clock += 1;
+
+		for key in grid.glyph_keys() {
+			match self.lut.get(&key) {
+				Some(&i) => self.slots[i as usize].last_used = self.clock,
+				None => self.insert(key)?
+			}
+		}
+
+		Ok(())
+	}
+
+	/// the slots that were rasterised since the last call
+	#[cfg(feature = "gpu")]
+	#[inline]
+	pub fn take_dirty(&mut self) -> Vec<u32> {
+		std::mem::take(&mut self.dirty)
+	}
+
+	// draws `key` into slot `i`, replacing whatever was there
+	fn rasterise(&mut self, key: GlyphKey, i: usize) {
+		let slot_len = self.slot_len();
+		let (font_width, font_height) = (self.font_width, self.font_height);
+
+		// wide glyphs are drawn two cells wide, then cut in half
+		let glyph_width = match key.span {
+			Span::Whole => font_width,
+			Span::Left | Span::Right => font_width * 2
+		};
+		let mut glyph = vec![0; glyph_width as usize * font_height as usize];
+
+		draw_glyph(&self.font, key.cluster, glyph_width, font_height, &mut glyph);
+
+		// there's only a regular face to work with, so bold and italic are synthesised
+		if key.attrs.contains(Attributes::ITALIC) {
+			shear(&mut glyph, glyph_width, baseline(&self.font, font_height));
+		}
+
+		if key.attrs.contains(Attributes::BOLD) {
+			embolden(&mut glyph, glyph_width, font_height);
+		}
+
+		let offset = match key.span {
+			Span::Whole | Span::Left => 0,
+			Span::Right => font_width as usize
+		};
+
+		let slot = &mut self.buffer[i * slot_len..(i + 1) * slot_len];
+		for (row, glyph_row) in slot.chunks_exact_mut(font_width as usize)
+			.zip(glyph.chunks_exact(glyph_width as usize))
+		{
+			row.copy_from_slice(&glyph_row[offset..offset + font_width as usize]);
+		}
+
+		draw_decorations(&self.decorations, &self.font, key.attrs, font_width, font_height, slot);
+	}
+
+	fn insert(&mut self, key: GlyphKey) -> Result<()> {
+		let full = self.max_glyphs.is_some_and(|max| self.slots.len() >= max.get());
+		if !full {
+			self.push(key);
+			return Ok(());
+		}
+
+		// glyphs used by the grid being prepared can't be evicted
+		let (i, _) = self.slots.iter()
+			.enumerate()
+			.filter(|(_, slot)| slot.last_used < self.clock)
+			.min_by_key(|(_, slot)| slot.last_used)
+			.ok_or(Error::AtlasFull)?;
+
+		self.lut.remove(&self.slots[i].key);
+		self.slots[i] = Slot {
+			key,
+			last_used: self.clock
+		};
+		self.lut.insert(key, i as u32);
+
+		self.rasterise(key, i);
+		self.dirty.push(i as u32);
+
+		Ok(())
+	}
+
+	fn push(&mut self, key: GlyphKey) {
+		let i = self.slots.len();
+
+		self.buffer.resize((i + 1) * self.slot_len(), 0);
+		self.rasterise(key, i);
+
+		self.slots.push(Slot {
+			key,
+			last_used: self.clock
+		});
+		self.lut.insert(key, i as u32);
+		self.dirty.push(i as u32);
 	}
 }
@@ -0,0 +1,145 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// clusters longer than a single character, leaked so that they outlive every id pointing at them
+#[derive(Default)]
+struct Table {
+	clusters: Vec<&'static str>,
+	ids: HashMap<&'static str, u32>
+}
+
+static TABLE: LazyLock<RwLock<Table>> = LazyLock::new(Default::default);
+
+// set on ids that index into `TABLE`, no `char` has this bit set
+const INTERNED: u32 = 1 << 31;
+
+// bounds on what gets leaked, so that untrusted text can't grow the table without limit
+const MAX_INTERNED: usize = 1 << 16;
+pub(crate) const MAX_LEN: usize = 64;
+
+/// a grapheme cluster, such as a base character followed by combining marks,
+/// a flag or a ZWJ emoji sequence
+///
+/// single characters are stored inline, longer clusters are interned for the life of the process,
+/// clusters over 64 bytes and any past the first 65536 interned ones are kept as their base character
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Cluster(u32);
+
+impl Cluster {
+	/// `None` unless `cluster` is exactly one extended grapheme cluster
+	pub fn new(cluster: &str) -> Option<Self> {
+		let mut chars = cluster.chars();
+		let first = chars.next()?;
+		if chars.next().is_none() {
+			return Some(Self::from(first));
+		}
+
+		if cluster.graphemes(true).nth(1).is_some() {
+			return None;
+		}
+
+		if cluster.len() > MAX_LEN {
+			return Some(Self::from(first));
+		}
+
+		if let Some(&id) = TABLE.read().unwrap().ids.get(cluster) {
+			return Some(Self(id | INTERNED));
+		}
+
+		// someone else may have interned it since the read lock was released
+		let mut table = TABLE.write().unwrap();
+		if let Some(&id) = table.ids.get(cluster) {
+			return Some(Self(id | INTERNED));
+		}
+
+		if table.clusters.len() >= MAX_INTERNED {
+			return Some(Self::from(first));
+		}
+
+		let id = table.clusters.len() as u32;
+		let cluster: &'static str = Box::leak(cluster.into());
+		table.clusters.push(cluster);
+		table.ids.insert(cluster, id);
+
+		Some(Self(id | INTERNED))
+	}
+
+	// the interned text, `None` for single characters
+	#[inline]
+	fn interned(self) -> Option<&'static str> {
+		match self.0 & INTERNED {
+			0 => None,
+			_ => Some(TABLE.read().unwrap().clusters[(self.0 & !INTERNED) as usize])
+		}
+	}
+
+	/// the first character of the cluster
+	pub fn base(self) -> char {
+		match self.interned() {
+			Some(cluster) => cluster.chars().next().expect("clusters are never empty"),
+			None => char::from_u32(self.0).expect("invariant upheld by type system")
+		}
+	}
+
+	pub fn chars(self) -> impl Iterator<Item = char> {
+		let (single, interned) = match self.interned() {
+			Some(cluster) => (None, Some(cluster.chars())),
+			None => (Some(self.base()), None)
+		};
+
+		single.into_iter().chain(interned.into_iter().flatten())
+	}
+
+	/// how many columns the cluster takes up, either 1 or 2
+	pub fn width(self) -> usize {
+		let width = match self.interned() {
+			Some(cluster) => cluster.width(),
+			None => self.base().width().unwrap_or(1)
+		};
+
+		width.clamp(1, 2)
+	}
+}
+
+impl From<char> for Cluster {
+	#[inline]
+	fn from(c: char) -> Self {
+		Self(c as u32)
+	}
+}
+
+impl fmt::Display for Cluster {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.interned() {
+			Some(cluster) => f.write_str(cluster),
+			None => fmt::Display::fmt(&self.base(), f)
+		}
+	}
+}
+
+impl fmt::Debug for Cluster {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Cluster({:?})", self.to_string())
+	}
+}
+
+// clusters are stored as their text, the interned ids differ from run to run
+#[cfg(feature = "serde")]
+impl serde::Serialize for Cluster {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Cluster {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::Error;
+
+		let cluster = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+		Self::new(&cluster).ok_or_else(|| D::Error::invalid_value(serde::de::Unexpected::Str(&cluster), &"a single grapheme cluster"))
+	}
+}
@@ -0,0 +1,181 @@
+/// a cell color, resolved against the sequence's [`Palette`] when the cell is drawn
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Color {
+	/// the palette's foreground or background, depending on where the color is used
+	#[default]
+	Default,
+	/// one of the palette's 256 colors, the first 16 being the ANSI colors
+	Indexed(u8),
+	/// a color given directly, ignoring the palette
+	Rgb(#[cfg_attr(feature = "serde", serde(with = "rgba"))] image::Rgba<u8>)
+}
+
+impl From<image::Rgba<u8>> for Color {
+	#[inline]
+	fn from(color: image::Rgba<u8>) -> Self {
+		Self::Rgb(color)
+	}
+}
+
+/// the colors that [`Color::Default`] and [`Color::Indexed`] stand for
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Palette {
+	#[cfg_attr(feature = "serde", serde(with = "rgba"))]
+	pub foreground: image::Rgba<u8>,
+	#[cfg_attr(feature = "serde", serde(with = "rgba"))]
+	pub background: image::Rgba<u8>,
+	/// the color of the cursor, `None` draws it by swapping its cell's colors
+	#[cfg_attr(feature = "serde", serde(with = "rgba::option"))]
+	pub cursor: Option<image::Rgba<u8>>,
+	/// the background of selected text
+	#[cfg_attr(feature = "serde", serde(with = "rgba::option"))]
+	pub selection: Option<image::Rgba<u8>>,
+	/// the 16 ANSI colors, then the 6x6x6 color cube and a 24 step grayscale ramp
+	#[cfg_attr(feature = "serde", serde(with = "rgba::array"))]
+	pub colors: [image::Rgba<u8>; 256]
+}
+
+#[inline]
+const fn rgb(r: u8, g: u8, b: u8) -> image::Rgba<u8> {
+	image::Rgba([r, g, b, u8::MAX])
+}
+
+// xterm's
+const ANSI: [image::Rgba<u8>; 16] = [
+	rgb(0x00, 0x00, 0x00),
+	rgb(0xcd, 0x00, 0x00),
+	rgb(0x00, 0xcd, 0x00),
+	rgb(0xcd, 0xcd, 0x00),
+	rgb(0x00, 0x00, 0xee),
+	rgb(0xcd, 0x00, 0xcd),
+	rgb(0x00, 0xcd, 0xcd),
+	rgb(0xe5, 0xe5, 0xe5),
+	rgb(0x7f, 0x7f, 0x7f),
+	rgb(0xff, 0x00, 0x00),
+	rgb(0x00, 0xff, 0x00),
+	rgb(0xff, 0xff, 0x00),
+	rgb(0x5c, 0x5c, 0xff),
+	rgb(0xff, 0x00, 0xff),
+	rgb(0x00, 0xff, 0xff),
+	rgb(0xff, 0xff, 0xff)
+];
+
+impl Default for Palette {
+	/// white on black, with xterm's colors
+	fn default() -> Self {
+		Self::with_ansi(rgb(0xff, 0xff, 0xff), rgb(0x00, 0x00, 0x00), ANSI)
+	}
+}
+
+impl Palette {
+	/// a palette with the given ANSI colors, and the standard cube and grayscale ramp above them
+	pub fn with_ansi(
+		foreground: image::Rgba<u8>,
+		background: image::Rgba<u8>,
+		ansi: [image::Rgba<u8>; 16]
+	) -> Self {
+		const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+		let colors = core::array::from_fn(|i| match i {
+			0..16 => ansi[i],
+			16..232 => {
+				let i = i - 16;
+				rgb(LEVELS[i / 36], LEVELS[(i / 6) % 6], LEVELS[i % 6])
+			},
+			_ => {
+				let level = 8 + ((i - 232) * 10) as u8;
+				rgb(level, level, level)
+			}
+		});
+
+		Self {
+			foreground,
+			background,
+			cursor: None,
+			selection: None,
+			colors
+		}
+	}
+
+	#[inline]
+	pub fn resolve_fg(&self, color: Color) -> image::Rgba<u8> {
+		self.resolve(color, self.foreground)
+	}
+
+	#[inline]
+	pub fn resolve_bg(&self, color: Color) -> image::Rgba<u8> {
+		self.resolve(color, self.background)
+	}
+
+	/// `None` if the cursor should take on the colors of the cell it's on
+	#[inline]
+	pub fn resolve_cursor(&self, color: Color) -> Option<image::Rgba<u8>> {
+		match color {
+			Color::Default => self.cursor,
+			color => Some(self.resolve(color, self.foreground))
+		}
+	}
+
+	#[inline]
+	fn resolve(&self, color: Color, default: image::Rgba<u8>) -> image::Rgba<u8> {
+		match color {
+			Color::Default => default,
+			Color::Indexed(i) => self.colors[i as usize],
+			Color::Rgb(color) => color
+		}
+	}
+}
+
+// colors are stored as `[r, g, b, a]`, `image` has no serde support of its own
+#[cfg(feature = "serde")]
+pub(crate) mod rgba {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	pub fn serialize<S: Serializer>(color: &image::Rgba<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+		color.0.serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<image::Rgba<u8>, D::Error> {
+		<[u8; 4]>::deserialize(deserializer).map(image::Rgba)
+	}
+
+	pub mod option {
+		use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+		pub fn serialize<S: Serializer>(color: &Option<image::Rgba<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+			color.map(|color| color.0).serialize(serializer)
+		}
+
+		pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<image::Rgba<u8>>, D::Error> {
+			Ok(Option::<[u8; 4]>::deserialize(deserializer)?.map(image::Rgba))
+		}
+	}
+
+	// serde only implements its traits for arrays of up to 32 elements
+	pub mod array {
+		use serde::de::Error;
+		use serde::{Deserialize, Deserializer, Serializer};
+
+		pub fn serialize<S: Serializer, const N: usize>(
+			colors: &[image::Rgba<u8>; N],
+			serializer: S
+		) -> Result<S::Ok, S::Error> {
+			serializer.collect_seq(colors.iter().map(|color| color.0))
+		}
+
+		pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+			deserializer: D
+		) -> Result<[image::Rgba<u8>; N], D::Error> {
+			let colors = Vec::<[u8; 4]>::deserialize(deserializer)?;
+			let len = colors.len();
+
+			colors.into_iter()
+				.map(image::Rgba)
+				.collect::<Vec<_>>()
+				.try_into()
+				.map_err(|_| D::Error::invalid_length(len, &format!("{N} colors").as_str()))
+		}
+	}
+}
@@ -0,0 +1,821 @@
+use crate::cluster::{self, Cluster};
+use crate::color::{Color, Palette};
+use crate::cursor::{Cursor, CursorShape};
+use crate::error::{Error, Result};
+use crate::grid::{Attributes, Grid, GridCell, GridPatch};
+use crate::sequence::{FontSize, Frame, GridSequence, KEYFRAME_INTERVAL};
+use core::num::{NonZeroU8, NonZeroUsize};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const MAGIC: [u8; 4] = *b"DVD\0";
+const VERSION: u16 = 1;
+
+// the records following the header, each deflate compressed on its own, readers skip kinds they don't know
+const GLYPHS: u8 = 0;
+const KEYFRAME: u8 = 1;
+const DELTA: u8 = 2;
+
+// upper bounds on what a record decompresses to, so that a corrupt file can't claim gigabytes of output
+const MAX_VARINT_LEN: u64 = 10;
+// the size, framerate, font size and palette
+const MAX_HEADER_LEN: u64 = (2 * MAX_VARINT_LEN) + 1 + 9 + 8 + (2 * 5) + (256 * 4);
+// flags, glyph, two rgba colors and attributes
+const MAX_CELL_LEN: u64 = 1 + MAX_VARINT_LEN + 4 + 4 + 2;
+// position, flags and an rgba color
+const MAX_CURSOR_LEN: u64 = (2 * MAX_VARINT_LEN) + 1 + 4;
+
+/// the settings every frame of a `.dvd` file shares
+#[derive(Clone, Debug, PartialEq)]
+pub struct DvdHeader {
+	pub width: NonZeroUsize,
+	pub height: NonZeroUsize,
+	pub framerate: NonZeroU8,
+	pub font_scale: FontSize,
+	pub palette: Palette
+}
+
+impl From<&GridSequence> for DvdHeader {
+	fn from(sequence: &GridSequence) -> Self {
+		let (width, height) = sequence.get_dimensions();
+
+		Self {
+			width,
+			height,
+			framerate: sequence.framerate,
+			font_scale: sequence.font_scale,
+			palette: sequence.palette.clone()
+		}
+	}
+}
+
+// little endian integers, with LEB128 for sizes and indices
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+	while n >= 0x80 {
+		buf.push((n as u8) | 0x80);
+		n >>= 7;
+	}
+
+	buf.push(n as u8);
+}
+
+#[inline]
+fn invalid(reason: &'static str) -> Error {
+	Error::InvalidContainer(reason)
+}
+
+// reads the fields of a decompressed record
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+	fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+		let (bytes, rest) = self.0.split_first_chunk().ok_or(invalid("truncated record"))?;
+		self.0 = rest;
+		Ok(*bytes)
+	}
+
+	#[inline]
+	fn u8(&mut self) -> Result<u8> {
+		self.array().map(|[b]| b)
+	}
+
+	fn varint(&mut self) -> Result<u64> {
+		let mut n = 0;
+
+		for shift in (0..64).step_by(7) {
+			let byte = self.u8()?;
+			n |= ((byte & 0x7f) as u64) << shift;
+
+			if byte & 0x80 == 0 {
+				return Ok(n);
+			}
+		}
+
+		Err(invalid("varint is too long"))
+	}
+
+	#[inline]
+	fn usize(&mut self) -> Result<usize> {
+		usize::try_from(self.varint()?).map_err(|_| invalid("size doesn't fit in memory"))
+	}
+
+	fn take(&mut self, len: usize) -> Result<&[u8]> {
+		if len > self.0.len() {
+			return Err(invalid("truncated record"));
+		}
+
+		let (bytes, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(bytes)
+	}
+
+	#[inline]
+	fn f32(&mut self) -> Result<f32> {
+		self.array().map(f32::from_le_bytes)
+	}
+
+	#[inline]
+	fn rgba(&mut self) -> Result<image::Rgba<u8>> {
+		self.array().map(image::Rgba)
+	}
+}
+
+// colors are split into a 2 bit tag, stored with the cell's other flags, and what follows it
+fn color_tag(color: Color) -> u8 {
+	match color {
+		Color::Default => 0,
+		Color::Indexed(_) => 1,
+		Color::Rgb(_) => 2
+	}
+}
+
+fn put_color(buf: &mut Vec<u8>, color: Color) {
+	match color {
+		Color::Default => (),
+		Color::Indexed(i) => buf.push(i),
+		Color::Rgb(color) => buf.extend_from_slice(&color.0)
+	}
+}
+
+fn read_color(bytes: &mut Bytes, tag: u8) -> Result<Color> {
+	match tag {
+		0 => Ok(Color::Default),
+		1 => bytes.u8().map(Color::Indexed),
+		2 => bytes.rgba().map(Color::Rgb),
+		_ => Err(invalid("unknown color"))
+	}
+}
+
+fn put_header(buf: &mut Vec<u8>, header: &DvdHeader) {
+	put_varint(buf, header.width.get() as u64);
+	put_varint(buf, header.height.get() as u64);
+	buf.push(header.framerate.get());
+
+	match header.font_scale {
+		FontSize::Pixel(s) => {
+			buf.push(0);
+			buf.extend_from_slice(&s.to_le_bytes());
+		},
+		FontSize::PixelXY { x, y } => {
+			buf.push(1);
+			buf.extend_from_slice(&x.to_le_bytes());
+			buf.extend_from_slice(&y.to_le_bytes());
+		},
+		FontSize::Point(pt) => {
+			buf.push(2);
+			buf.extend_from_slice(&pt.to_le_bytes());
+		}
+	}
+
+	let palette = &header.palette;
+	buf.extend_from_slice(&palette.foreground.0);
+	buf.extend_from_slice(&palette.background.0);
+
+	for color in [palette.cursor, palette.selection] {
+		buf.push(color.is_some() as u8);
+		buf.extend_from_slice(&color.map_or([0; 4], |color| color.0));
+	}
+
+	for color in &palette.colors {
+		buf.extend_from_slice(&color.0);
+	}
+}
+
+fn read_header(bytes: &mut Bytes) -> Result<DvdHeader> {
+	let mut dimension = || NonZeroUsize::new(bytes.usize()?).ok_or(invalid("grid has no cells"));
+	let (width, height) = (dimension()?, dimension()?);
+	width.checked_mul(height).ok_or(invalid("grid is too large"))?;
+	let framerate = NonZeroU8::new(bytes.u8()?).ok_or(invalid("framerate is 0"))?;
+
+	let font_scale = match bytes.u8()? {
+		0 => FontSize::Pixel(bytes.f32()?),
+		1 => FontSize::PixelXY {
+			x: bytes.f32()?,
+			y: bytes.f32()?
+		},
+		2 => FontSize::Point(bytes.f32()?),
+		_ => return Err(invalid("unknown font size"))
+	};
+
+	let mut palette = Palette {
+		foreground: bytes.rgba()?,
+		background: bytes.rgba()?,
+		..Palette::default()
+	};
+
+	for color in [&mut palette.cursor, &mut palette.selection] {
+		let set = bytes.u8()? != 0;
+		*color = Some(bytes.rgba()?).filter(|_| set);
+	}
+
+	for color in &mut palette.colors {
+		*color = bytes.rgba()?;
+	}
+
+	Ok(DvdHeader {
+		width,
+		height,
+		framerate,
+		font_scale,
+		palette
+	})
+}
+
+fn put_cursor(buf: &mut Vec<u8>, cursor: &Cursor) {
+	let shape = match cursor.shape {
+		CursorShape::Block => 0,
+		CursorShape::HollowBlock => 1,
+		CursorShape::Bar => 2,
+		CursorShape::Underline => 3
+	};
+
+	put_varint(buf, cursor.x as u64);
+	put_varint(buf, cursor.y as u64);
+	buf.push(cursor.visible as u8 | (shape << 1) | (color_tag(cursor.color) << 3));
+	put_color(buf, cursor.color);
+}
+
+fn read_cursor(bytes: &mut Bytes) -> Result<Cursor> {
+	let (x, y) = (bytes.usize()?, bytes.usize()?);
+	let flags = bytes.u8()?;
+
+	let shape = match (flags >> 1) & 0b11 {
+		0 => CursorShape::Block,
+		1 => CursorShape::HollowBlock,
+		2 => CursorShape::Bar,
+		_ => CursorShape::Underline
+	};
+
+	Ok(Cursor {
+		x,
+		y,
+		visible: flags & 1 != 0,
+		shape,
+		color: read_color(bytes, flags >> 3)?
+	})
+}
+
+/// writes a sequence to a `.dvd` file as it's recorded
+///
+/// frames are written as deltas on the frame before them, with a keyframe whenever a delta
+/// would replace most of the grid and at least every 64 frames
+pub struct DvdWriter<W: Write> {
+	writer: W,
+	width: NonZeroUsize,
+	height: NonZeroUsize,
+	// the index each cluster written so far is stored as
+	glyphs: HashMap<Cluster, u64>,
+	// the last frame's grid, which the next frame is diffed against
+	tail: Option<Grid>,
+	since_keyframe: usize
+}
+
+impl<W: Write> DvdWriter<W> {
+	/// writes `header`, every frame appended after it has to be the header's size
+	pub fn new(mut writer: W, header: &DvdHeader) -> Result<Self> {
+		let mut buf = Vec::new();
+		put_header(&mut buf, header);
+
+		writer.write_all(&MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+
+		let mut dvd = Self {
+			writer,
+			width: header.width,
+			height: header.height,
+			glyphs: HashMap::new(),
+			tail: None,
+			since_keyframe: 0
+		};
+		dvd.write_payload(&buf)?;
+
+		Ok(dvd)
+	}
+
+	pub fn append(&mut self, frame: &Frame) -> Result<()> {
+		let grid = frame.grid();
+		if grid.width() != self.width.get() || grid.height() != self.height.get() {
+			return Err(Error::MismatchedGrid);
+		}
+
+		self.write_glyphs(grid)?;
+
+		let patch = self.tail.as_ref()
+			.filter(|_| self.since_keyframe < KEYFRAME_INTERVAL)
+			.map(|tail| tail.diff(grid))
+			.filter(|patch| patch.len() <= grid.as_slice().len() / 2);
+
+		let mut buf = vec![frame.frame_hold.get()];
+
+		let kind = match patch {
+			Some(patch) => {
+				buf.push(patch.cursor.is_some() as u8);
+				if let Some(cursor) = &patch.cursor {
+					put_cursor(&mut buf, cursor);
+				}
+
+				// runs are stored by their distance from the end of the run before them
+				put_varint(&mut buf, patch.runs.len() as u64);
+				let mut end = 0;
+				for &(start, len) in &patch.runs {
+					put_varint(&mut buf, (start - end) as u64);
+					put_varint(&mut buf, len as u64);
+					end = start + len;
+				}
+
+				self.put_cells(&mut buf, &patch.cells);
+				self.since_keyframe += 1;
+				DELTA
+			},
+			None => {
+				put_cursor(&mut buf, grid.cursor());
+				self.put_cells(&mut buf, grid.as_slice());
+				self.since_keyframe = 0;
+				KEYFRAME
+			}
+		};
+
+		self.write_record(kind, &buf)?;
+		self.tail = Some(grid.clone());
+
+		Ok(())
+	}
+
+	/// appends every frame of `sequence`, which should have the header's size
+	pub fn append_sequence(&mut self, sequence: &GridSequence) -> Result<()> {
+		for frame in sequence.iter() {
+			self.append(&frame)?;
+		}
+
+		Ok(())
+	}
+
+	#[inline]
+	pub fn flush(&mut self) -> Result<()> {
+		self.writer.flush().map_err(Error::from)
+	}
+
+	/// flushes the file and returns what it was written to
+	pub fn into_inner(mut self) -> Result<W> {
+		self.flush()?;
+		Ok(self.writer)
+	}
+
+	// a glyph table for the clusters in `grid` that haven't been written yet
+	fn write_glyphs(&mut self, grid: &Grid) -> Result<()> {
+		let first = self.glyphs.len() as u64;
+		let new: Vec<Cluster> = grid.clusters()
+			.into_iter()
+			.filter(|cluster| !self.glyphs.contains_key(cluster))
+			.collect();
+
+		if new.is_empty() {
+			return Ok(());
+		}
+
+		let mut buf = Vec::new();
+		put_varint(&mut buf, first);
+		put_varint(&mut buf, new.len() as u64);
+
+		for (i, cluster) in new.into_iter().enumerate() {
+			let text = cluster.to_string();
+			put_varint(&mut buf, text.len() as u64);
+			buf.extend_from_slice(text.as_bytes());
+
+			self.glyphs.insert(cluster, first + i as u64);
+		}
+
+		self.write_record(GLYPHS, &buf)
+	}
+
+	fn put_cells(&self, buf: &mut Vec<u8>, cells: &[GridCell]) {
+		for cell in cells {
+			let attrs = cell.attributes();
+			buf.push(cell.width_code()
+				| (color_tag(cell.fg_color()) << 2)
+				| (color_tag(cell.bg_color()) << 4)
+				| ((!attrs.is_empty() as u8) << 6));
+
+			put_varint(buf, self.glyphs[&cell.cluster()]);
+			put_color(buf, cell.fg_color());
+			put_color(buf, cell.bg_color());
+
+			if !attrs.is_empty() {
+				buf.extend_from_slice(&attrs.bits().to_le_bytes());
+			}
+		}
+	}
+
+	fn write_record(&mut self, kind: u8, payload: &[u8]) -> Result<()> {
+		self.writer.write_all(&[kind])?;
+		self.write_payload(payload)
+	}
+
+	// the compressed length, then the compressed payload
+	fn write_payload(&mut self, payload: &[u8]) -> Result<()> {
+		let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+		encoder.write_all(payload)?;
+		let compressed = encoder.finish()?;
+
+		let len = u32::try_from(compressed.len()).map_err(|_| invalid("record is over 4GiB"))?;
+		self.writer.write_all(&len.to_le_bytes())?;
+		self.writer.write_all(&compressed)?;
+
+		Ok(())
+	}
+}
+
+/// reads the frames of a `.dvd` file one at a time
+///
+/// to render them, create a renderer with [`DvdReader::sequence`] and pass it each frame with `render_frame`
+pub struct DvdReader<R: Read> {
+	reader: R,
+	header: DvdHeader,
+	glyphs: Vec<Cluster>,
+	// the last frame's grid, which deltas apply to
+	grid: Option<Grid>
+}
+
+impl<R: Read> DvdReader<R> {
+	/// reads the header, the frames are read as they're asked for
+	pub fn new(mut reader: R) -> Result<Self> {
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic)?;
+		if magic != MAGIC {
+			return Err(invalid("not a .dvd file"));
+		}
+
+		let mut version = [0; 2];
+		reader.read_exact(&mut version)?;
+		let version = u16::from_le_bytes(version);
+		if version > VERSION {
+			return Err(Error::UnsupportedVersion(version));
+		}
+
+		let header = read_header(&mut Bytes(&read_payload(&mut reader, MAX_HEADER_LEN)?))?;
+
+		Ok(Self {
+			reader,
+			header,
+			glyphs: Vec::new(),
+			grid: None
+		})
+	}
+
+	#[inline]
+	pub fn header(&self) -> &DvdHeader {
+		&self.header
+	}
+
+	/// an empty sequence with the file's settings, which cells are sized for every glyph read so far
+	pub fn sequence(&self) -> GridSequence {
+		let header = &self.header;
+		let mut sequence = GridSequence::new(header.width, header.height, header.font_scale);
+		sequence.framerate = header.framerate;
+		sequence.palette = header.palette.clone();
+		sequence.extend_glyph_set(self.glyphs.iter().copied());
+
+		sequence
+	}
+
+	/// the next frame, `None` at the end of the file
+	pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+		while let Some(kind) = self.read_kind()? {
+			let limit = self.max_record_len();
+			let payload = read_payload(&mut self.reader, limit)?;
+			let mut bytes = Bytes(&payload);
+
+			match kind {
+				GLYPHS => self.read_glyphs(&mut bytes)?,
+				KEYFRAME | DELTA => return self.read_frame(kind, &mut bytes).map(Some),
+				_ => ()
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// reads the rest of the file into a sequence
+	pub fn into_sequence(mut self) -> Result<GridSequence> {
+		let mut sequence = self.sequence();
+
+		while let Some(frame) = self.next_frame()? {
+			sequence.append(frame);
+		}
+
+		Ok(sequence)
+	}
+
+	// the most any record can hold for the header's grid size: a frame with every cell in a run
+	// of its own, or a glyph table with a new glyph in every cell
+	fn max_record_len(&self) -> u64 {
+		let cells = (self.header.width.get() as u64).saturating_mul(self.header.height.get() as u64);
+		let frame = cells.saturating_mul((2 * MAX_VARINT_LEN) + MAX_CELL_LEN);
+		let glyphs = cells.saturating_mul(MAX_VARINT_LEN + cluster::MAX_LEN as u64);
+
+		frame.max(glyphs).saturating_add(3 + MAX_CURSOR_LEN + (2 * MAX_VARINT_LEN))
+	}
+
+	// the kind of the next record, `None` at the end of the file
+	fn read_kind(&mut self) -> Result<Option<u8>> {
+		let mut kind = [0];
+
+		match self.reader.read(&mut kind)? {
+			0 => Ok(None),
+			_ => Ok(Some(kind[0]))
+		}
+	}
+
+	fn read_glyphs(&mut self, bytes: &mut Bytes) -> Result<()> {
+		let first = bytes.usize()?;
+		let count = bytes.usize()?;
+
+		// tables read ahead by `read_glyph_table` are read again along with the frames
+		if first > self.glyphs.len() {
+			return Err(invalid("glyph table is out of order"));
+		}
+
+		for id in first..first.saturating_add(count) {
+			let len = bytes.usize()?;
+			let text = core::str::from_utf8(bytes.take(len)?).map_err(|_| invalid("glyph isn't utf-8"))?;
+
+			if id == self.glyphs.len() {
+				self.glyphs.push(Cluster::new(text).ok_or(invalid("glyph isn't a single grapheme cluster"))?);
+			}
+		}
+
+		Ok(())
+	}
+
+	fn read_frame(&mut self, kind: u8, bytes: &mut Bytes) -> Result<Frame> {
+		let frame_hold = NonZeroU8::new(bytes.u8()?).ok_or(invalid("frame is held for 0 frames"))?;
+		let (width, height) = (self.header.width.get(), self.header.height.get());
+
+		if kind == KEYFRAME {
+			let cursor = read_cursor(bytes)?;
+			let cells = self.read_cells(bytes, width * height)?;
+			let grid = Grid::from_cells(cells, width, height, cursor).expect("every cell was read");
+
+			return Ok(Frame::variable(self.grid.insert(grid).clone(), frame_hold));
+		}
+
+		let cursor = match bytes.u8()? {
+			0 => None,
+			_ => Some(read_cursor(bytes)?)
+		};
+
+		let mut runs = Vec::new();
+		let mut end = 0usize;
+		for _ in 0..bytes.usize()? {
+			let start = end.checked_add(bytes.usize()?);
+			let len = bytes.usize()?;
+			end = start.and_then(|start| start.checked_add(len))
+				.filter(|&end| end <= width * height)
+				.ok_or(invalid("delta is outside the grid"))?;
+
+			runs.push((end - len, len));
+		}
+
+		let cells = self.read_cells(bytes, runs.iter().map(|&(_, len)| len).sum())?;
+		let grid = self.grid.as_mut().ok_or(invalid("delta before the first keyframe"))?;
+		grid.apply(&GridPatch {
+			width,
+			height,
+			runs,
+			cells,
+			cursor
+		});
+
+		Ok(Frame::variable(grid.clone(), frame_hold))
+	}
+
+	fn read_cells(&self, bytes: &mut Bytes, count: usize) -> Result<Vec<GridCell>> {
+		// each cell takes at least 2 bytes, which bounds what a corrupt count can allocate
+		let mut cells = Vec::with_capacity(count.min(bytes.0.len() / 2));
+
+		for _ in 0..count {
+			let flags = bytes.u8()?;
+			let cluster = usize::try_from(bytes.varint()?).ok()
+				.and_then(|id| self.glyphs.get(id))
+				.ok_or(invalid("cell refers to a missing glyph"))?;
+
+			let fg_color = read_color(bytes, (flags >> 2) & 0b11)?;
+			let bg_color = read_color(bytes, (flags >> 4) & 0b11)?;
+
+			let mut cell = GridCell::new_full_color(*cluster, fg_color, bg_color)
+				.with_width_code(flags & 0b11)
+				.ok_or(invalid("unknown cell width"))?;
+
+			if flags & (1 << 6) != 0 {
+				cell.set_attributes(Attributes::from_bits(u16::from_le_bytes(bytes.array()?)));
+			}
+
+			cells.push(cell);
+		}
+
+		Ok(cells)
+	}
+}
+
+impl<R: Read + Seek> DvdReader<R> {
+	/// reads every glyph table in the file, skipping over the frames without decompressing them,
+	/// so that [`DvdReader::sequence`] sizes cells for the whole recording
+	pub fn read_glyph_table(&mut self) -> Result<()> {
+		let position = self.reader.stream_position()?;
+
+		while let Some(kind) = self.read_kind()? {
+			if kind == GLYPHS {
+				let limit = self.max_record_len();
+				let payload = read_payload(&mut self.reader, limit)?;
+				self.read_glyphs(&mut Bytes(&payload))?;
+				continue;
+			}
+
+			let mut len = [0; 4];
+			self.reader.read_exact(&mut len)?;
+			self.reader.seek(SeekFrom::Current(u32::from_le_bytes(len) as i64))?;
+		}
+
+		self.reader.seek(SeekFrom::Start(position))?;
+		Ok(())
+	}
+}
+
+impl<R: Read> Iterator for DvdReader<R> {
+	type Item = Result<Frame>;
+
+	#[inline]
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_frame().transpose()
+	}
+}
+
+// a compressed length and payload, decompressed, which is an error if it's over `limit` bytes
+fn read_payload(reader: &mut impl Read, limit: u64) -> Result<Vec<u8>> {
+	let mut len = [0; 4];
+	reader.read_exact(&mut len)?;
+	let len = u32::from_le_bytes(len) as u64;
+
+	let mut compressed = Vec::new();
+	reader.take(len).read_to_end(&mut compressed)?;
+	if compressed.len() as u64 != len {
+		return Err(invalid("truncated record"));
+	}
+
+	let mut payload = Vec::new();
+	DeflateDecoder::new(compressed.as_slice()).take(limit.saturating_add(1)).read_to_end(&mut payload)?;
+	if payload.len() as u64 > limit {
+		return Err(invalid("record is too large for the grid"));
+	}
+
+	Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::grid::{Rect, Underline};
+
+	#[test]
+	fn round_trip() {
+		let header = DvdHeader {
+			width: NonZeroUsize::new(8).unwrap(),
+			height: NonZeroUsize::new(3).unwrap(),
+			framerate: NonZeroU8::new(30).unwrap(),
+			font_scale: FontSize::Pixel(16.0),
+			palette: Palette {
+				cursor: Some(image::Rgba([255, 0, 0, 255])),
+				..Palette::default()
+			}
+		};
+
+		// text typed a character at a time, then replaced wholesale so that a keyframe is written
+		let mut grid = Grid::new(8, 3);
+		let mut frames = Vec::new();
+
+		{
+			let mut writer = grid.writer();
+			writer.set_fg_color(Color::Indexed(2));
+			writer.set_bg_color(image::Rgba([10, 20, 30, 255]));
+			writer.set_attributes(Attributes::BOLD.with_underline(Underline::Curly));
+		}
+
+		for (i, text) in ["a", "e\u{301}", "漢", "🇳🇱", "b"].into_iter().enumerate() {
+			grid.writer().write_str(text);
+			grid.set_cursor(Cursor::new(i + 1, 0, CursorShape::Bar));
+			frames.push(Frame::variable(grid.clone(), NonZeroU8::new(i as u8 + 1).unwrap()));
+		}
+
+		grid.fill_rect(Rect::new(0, 0, 8, 3), GridCell::new('#'));
+		frames.push(Frame::single(grid.clone()));
+
+		grid.set(7, 2, GridCell::new('字'));
+		grid.cursor_mut().visible = false;
+		frames.push(Frame::single(grid));
+
+		let mut dvd = DvdWriter::new(Vec::new(), &header).unwrap();
+		for frame in &frames {
+			dvd.append(frame).unwrap();
+		}
+		let bytes = dvd.into_inner().unwrap();
+
+		let reader = DvdReader::new(bytes.as_slice()).unwrap();
+		assert_eq!(reader.header(), &header);
+
+		let read: Vec<Frame> = reader.collect::<Result<_>>().unwrap();
+		assert_eq!(read.len(), frames.len());
+
+		for (read, frame) in read.iter().zip(&frames) {
+			assert!(read.grid() == frame.grid());
+			assert_eq!(read.frame_hold, frame.frame_hold);
+		}
+	}
+
+	#[test]
+	fn truncated() {
+		let mut sequence = GridSequence::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(2).unwrap(), FontSize::Pixel(16.0));
+		let mut grid = Grid::new(4, 2);
+
+		for text in ["a", "b", "漢", "\nc"] {
+			grid.writer().write_str(text);
+			sequence.append(Frame::single(grid.clone()));
+		}
+
+		let mut dvd = DvdWriter::new(Vec::new(), &DvdHeader::from(&sequence)).unwrap();
+		dvd.append_sequence(&sequence).unwrap();
+		let bytes = dvd.into_inner().unwrap();
+
+		// cutting between records loses frames, cutting inside one is an error
+		for len in 0..bytes.len() {
+			if let Ok(read) = DvdReader::new(&bytes[..len]).and_then(DvdReader::into_sequence) {
+				assert!(read.len() < sequence.len());
+			}
+		}
+
+		assert!(DvdReader::new(&bytes[..bytes.len() - 1]).and_then(DvdReader::into_sequence).is_err());
+	}
+
+	#[test]
+	fn delta_outside_grid() {
+		let sequence = GridSequence::new(NonZeroUsize::new(8).unwrap(), NonZeroUsize::new(3).unwrap(), FontSize::Pixel(16.0));
+		let mut dvd = DvdWriter::new(Vec::new(), &DvdHeader::from(&sequence)).unwrap();
+		dvd.append(&Frame::single(Grid::new(8, 3))).unwrap();
+
+		// a single run one cell longer than the grid
+		let mut buf = vec![1, 0];
+		put_varint(&mut buf, 1);
+		put_varint(&mut buf, 0);
+		put_varint(&mut buf, (8 * 3) + 1);
+		dvd.write_record(DELTA, &buf).unwrap();
+
+		let bytes = dvd.into_inner().unwrap();
+		let mut reader = DvdReader::new(bytes.as_slice()).unwrap();
+		assert!(reader.next_frame().unwrap().is_some());
+		assert!(matches!(reader.next_frame(), Err(Error::InvalidContainer("delta is outside the grid"))));
+	}
+
+	#[test]
+	fn delta_splitting_wide_cell() {
+		let sequence = GridSequence::new(NonZeroUsize::new(8).unwrap(), NonZeroUsize::new(3).unwrap(), FontSize::Pixel(16.0));
+		let mut grid = Grid::new(8, 3);
+		grid.set(0, 0, GridCell::new('漢'));
+
+		let mut dvd = DvdWriter::new(Vec::new(), &DvdHeader::from(&sequence)).unwrap();
+		dvd.append(&Frame::single(grid)).unwrap();
+
+		// overwrites only the continuation with a space, which the keyframe's glyph table defines
+		let mut buf = vec![1, 0];
+		put_varint(&mut buf, 1);
+		put_varint(&mut buf, 1);
+		put_varint(&mut buf, 1);
+		buf.push(0);
+		put_varint(&mut buf, dvd.glyphs[&Cluster::from(' ')]);
+		dvd.write_record(DELTA, &buf).unwrap();
+
+		let bytes = dvd.into_inner().unwrap();
+		let mut reader = DvdReader::new(bytes.as_slice()).unwrap();
+		reader.next_frame().unwrap();
+		let frame = reader.next_frame().unwrap().unwrap();
+
+		// the wide half left behind is blanked
+		assert_eq!(frame.grid().get_cell(0, 0).character(), ' ');
+		assert!(!frame.grid().get_cell(0, 0).is_wide());
+	}
+
+	#[test]
+	fn oversized_record() {
+		let sequence = GridSequence::new(NonZeroUsize::MIN, NonZeroUsize::MIN, FontSize::Pixel(16.0));
+		let mut dvd = DvdWriter::new(Vec::new(), &DvdHeader::from(&sequence)).unwrap();
+		dvd.append(&Frame::single(Grid::new(1, 1))).unwrap();
+
+		// compresses to almost nothing, but a 1x1 grid can't need this much
+		dvd.write_record(DELTA, &[0; 1 << 20]).unwrap();
+
+		let bytes = dvd.into_inner().unwrap();
+		let mut reader = DvdReader::new(bytes.as_slice()).unwrap();
+		assert!(reader.next_frame().unwrap().is_some());
+		assert!(matches!(reader.next_frame(), Err(Error::InvalidContainer("record is too large for the grid"))));
+	}
+}
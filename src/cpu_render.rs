@@ -1,13 +1,403 @@
-use crate::sequence::GridSequence;
+use crate::atlas::{Atlas, check_grid, populate_atlas};
+use crate::color::Palette;
+use crate::cursor::CursorShape;
+use crate::error::{Error, Result};
+use crate::grid::{Grid, GridCell};
+use crate::render::{RenderedFrame, VideoSrc};
+use crate::sequence::{Frame, GridSequence};
+use ab_glyph::Font;
+use core::num::{NonZeroU8, NonZeroUsize};
 
-/*
-pub trait VideoSrc: Iterator<Item = RenderedFrame> + Send + 'static + private::Sealed {
-	fn framerate(&self) -> NonZeroU8;
-	fn width(&self) -> u32;
-	fn height(&self) -> u32;
+/// renders in software, producing the same frames as the gpu renderer
+///
+/// with the `parallel` feature, each row of cells is rendered as its own band on the current `rayon` thread pool
+pub struct CpuRenderer {
+	sequence: GridSequence,
+	atlas: Atlas,
+	output_width: u32,
+	output_height: u32,
+	error: Option<Error>
 }
-*/
 
-pub struct CpuRenderer {
-	sequence: GridSequence
+// exact `n / 255` for any `n <= 255 * 255`, without a division the compiler can't vectorise
+#[inline(always)]
+fn div_255(n: u32) -> u32 {
+	(n + 1 + (n >> 8)) >> 8
+}
+
+// blends a horizontal span of pixels between `fg` and `bg` by coverage,
+// bit-identical to `qlerp` in `shader.wgsl`
+#[inline]
+fn blend_span(out: &mut [u8], coverage: &[u8], fg: [u8; 4], bg: [u8; 4]) {
+	// weighting by alpha reduces to a plain lerp when both colors are opaque
+	if fg[3] == u8::MAX && bg[3] == u8::MAX {
+		for (px, &t) in out.chunks_exact_mut(4).zip(coverage) {
+			let t = t as u32;
+
+			for i in 0..4 {
+				let a1 = fg[i] as u32 * t;
+				let b1 = bg[i] as u32 * (255 - t);
+
+				px[i] = div_255(a1 + b1) as u8;
+			}
+		}
+
+		return;
+	}
+
+	for (px, &t) in out.chunks_exact_mut(4).zip(coverage) {
+		let t = t as u32;
+		let wa = fg[3] as u32 * t;
+		let wb = bg[3] as u32 * (255 - t);
+		let w = wa + wb;
+
+		if w == 0 {
+			px.fill(0);
+			continue;
+		}
+
+		for i in 0..3 {
+			px[i] = ((fg[i] as u32 * wa + bg[i] as u32 * wb) / w) as u8;
+		}
+		px[3] = div_255(w) as u8;
+	}
+}
+
+impl CpuRenderer {
+	pub fn new<F: Font + Send + Sync + 'static>(font: F, sequence: GridSequence) -> Result<Self> {
+		let (width, height) = sequence.get_dimensions();
+		let atlas = populate_atlas(font, &sequence)?;
+
+		let output_width = width.get() as u32 * atlas.font_width;
+		let output_height = height.get() as u32 * atlas.font_height;
+
+		Ok(Self {
+			sequence,
+			atlas,
+			output_width,
+			output_height,
+			error: None
+		})
+	}
+
+	/// the frames that haven't been rendered yet
+	#[inline]
+	pub fn sequence(&self) -> &GridSequence {
+		&self.sequence
+	}
+
+	/// cap the number of glyphs kept rasterised, evicting the least recently used ones beyond it
+	#[inline]
+	pub fn set_max_glyphs(&mut self, max_glyphs: Option<NonZeroUsize>) {
+		self.atlas.max_glyphs = max_glyphs;
+	}
+
+	/// the palette default and indexed colors are drawn with, replacing the sequence's
+	#[inline]
+	pub fn set_palette(&mut self, palette: Palette) {
+		self.sequence.palette = palette;
+	}
+
+	/// render any frame, without consuming the sequence
+	pub fn render_frame(&mut self, frame: &Frame) -> Result<RenderedFrame> {
+		check_grid(&self.sequence, frame.grid())?;
+
+		Ok(RenderedFrame {
+			img: self.render(frame.grid())?,
+			frame_hold: frame.frame_hold
+		})
+	}
+
+	/// render a single grid, which is held for one frame
+	pub fn render_grid(&mut self, grid: &Grid) -> Result<RenderedFrame> {
+		check_grid(&self.sequence, grid)?;
+
+		Ok(RenderedFrame {
+			img: self.render(grid)?,
+			frame_hold: NonZeroU8::MIN
+		})
+	}
+
+	// renders a single row of cells into `out`, which spans `font_height` rows of pixels
+	fn render_row(&self, row: &[GridCell], out: &mut [u8]) {
+		let font_width = self.atlas.font_width as usize;
+		let font_height = self.atlas.font_height as usize;
+		let row_bytes = self.output_width as usize * 4;
+
+		for (x, cell) in row.iter().enumerate() {
+			let aidx = *self.atlas.lut.get(&cell.glyph_key())
+				.expect("invariant upheld by type system") as usize;
+			let (fg_color, bg_color) = cell.display_colors(&self.sequence.palette);
+			let (fg_color, bg_color) = (fg_color.0, bg_color.0);
+
+			for py in 0..font_height {
+				let atlas_start = ((aidx * font_height) + py) * font_width;
+				let coverage = &self.atlas.buffer[atlas_start..atlas_start + font_width];
+
+				let out_start = (py * row_bytes) + (x * font_width * 4);
+				let out_px = &mut out[out_start..out_start + (font_width * 4)];
+
+				blend_span(out_px, coverage, fg_color, bg_color);
+			}
+		}
+	}
+
+	// draws the cursor over the cells it covers, the same way `sample_atlas` does
+	fn draw_cursor(&self, grid: &Grid, out: &mut [u8]) {
+		let palette = &self.sequence.palette;
+		let Some(cursor) = grid.cursor_span(palette) else {
+			return;
+		};
+
+		let font_width = self.atlas.font_width as usize;
+		let font_height = self.atlas.font_height as usize;
+		let row_bytes = self.output_width as usize * 4;
+		let thickness = self.atlas.cursor_thickness as usize;
+		let cursor_width = cursor.columns * font_width;
+
+		for column in 0..cursor.columns {
+			let cell = grid.get_cell(cursor.x + column, cursor.y);
+			let aidx = *self.atlas.lut.get(&cell.glyph_key())
+				.expect("invariant upheld by type system") as usize;
+			let (fg_color, bg_color) = cell.display_colors(palette);
+			let color = cursor.color.unwrap_or(fg_color).0;
+
+			for py in 0..font_height {
+				let out_start = (((cursor.y * font_height) + py) * row_bytes) + ((cursor.x + column) * font_width * 4);
+				let out_px = &mut out[out_start..out_start + (font_width * 4)];
+
+				if cursor.shape == CursorShape::Block {
+					let atlas_start = ((aidx * font_height) + py) * font_width;
+					let coverage = &self.atlas.buffer[atlas_start..atlas_start + font_width];
+
+					blend_span(out_px, coverage, bg_color.0, color);
+					continue;
+				}
+
+				for (px, out_px) in out_px.chunks_exact_mut(4).enumerate() {
+					let x = (column * font_width) + px;
+					if cursor.shape.covers(x, py, cursor_width, font_height, thickness) {
+						out_px.copy_from_slice(&color);
+					}
+				}
+			}
+		}
+	}
+
+	fn render(&mut self, grid: &Grid) -> Result<image::RgbaImage> {
+		self.atlas.prepare(grid)?;
+
+		let band_bytes = self.output_width as usize * self.atlas.font_height as usize * 4;
+		let mut buf = vec![0; band_bytes * grid.height()];
+
+		#[cfg(feature = "parallel")]
+		{
+			use rayon::prelude::*;
+
+			buf.par_chunks_exact_mut(band_bytes)
+				.zip(grid.as_slice().par_chunks_exact(grid.width()))
+				.for_each(|(band, row)| self.render_row(row, band));
+		}
+
+		#[cfg(not(feature = "parallel"))]
+		for (row, band) in grid.rows().zip(buf.chunks_exact_mut(band_bytes)) {
+			self.render_row(row, band);
+		}
+
+		self.draw_cursor(grid, &mut buf);
+
+		// `check_grid` made sure the grid is the sequence's size, which the output is sized for
+		Ok(image::RgbaImage::from_raw(self.output_width, self.output_height, buf)
+			.expect("a band of pixels for every row of the grid fills the output"))
+	}
+}
+
+impl Iterator for CpuRenderer {
+	type Item = RenderedFrame;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.error.is_some() {
+			return None;
+		}
+
+		let frame = self.sequence.pop()?;
+		let rendered = check_grid(&self.sequence, frame.grid())
+			.and_then(|_| self.render(frame.grid()));
+
+		match rendered {
+			Ok(img) => Some(RenderedFrame {
+				img,
+				frame_hold: frame.frame_hold
+			}),
+			Err(e) => {
+				// ends iteration, the error can be retrieved with `VideoSrc::take_error`
+				self.error = Some(e);
+				None
+			}
+		}
+	}
+}
+
+impl VideoSrc for CpuRenderer {
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		self.sequence.framerate
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		self.output_width
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		self.output_height
+	}
+
+	#[inline]
+	fn take_error(&mut self) -> Option<Error> {
+		self.error.take()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sequence::{Frame, Px};
+	use ab_glyph::{GlyphId, Outline, OutlineCurve, Rect, point};
+	use core::num::NonZeroUsize;
+
+	// every character but space is a solid box, filling its advance from the descent to the ascent
+	struct BoxFont;
+
+	impl Font for BoxFont {
+		fn units_per_em(&self) -> Option<f32> {
+			Some(1000.0)
+		}
+
+		fn ascent_unscaled(&self) -> f32 {
+			800.0
+		}
+
+		fn descent_unscaled(&self) -> f32 {
+			-200.0
+		}
+
+		fn line_gap_unscaled(&self) -> f32 {
+			0.0
+		}
+
+		fn glyph_id(&self, c: char) -> GlyphId {
+			GlyphId((c != ' ') as u16)
+		}
+
+		fn h_advance_unscaled(&self, _: GlyphId) -> f32 {
+			600.0
+		}
+
+		fn h_side_bearing_unscaled(&self, _: GlyphId) -> f32 {
+			0.0
+		}
+
+		fn v_advance_unscaled(&self, _: GlyphId) -> f32 {
+			1000.0
+		}
+
+		fn v_side_bearing_unscaled(&self, _: GlyphId) -> f32 {
+			0.0
+		}
+
+		fn kern_unscaled(&self, _: GlyphId, _: GlyphId) -> f32 {
+			0.0
+		}
+
+		fn outline(&self, id: GlyphId) -> Option<Outline> {
+			let corners = [point(0.0, -200.0), point(600.0, -200.0), point(600.0, 800.0), point(0.0, 800.0)];
+
+			(id.0 == 1).then(|| Outline {
+				// outline bounds run from the top left, y up
+				bounds: Rect {
+					min: point(0.0, 800.0),
+					max: point(600.0, -200.0)
+				},
+				curves: (0..4).map(|i| OutlineCurve::Line(corners[i], corners[(i + 1) % 4])).collect()
+			})
+		}
+
+		fn glyph_count(&self) -> usize {
+			2
+		}
+
+		fn codepoint_ids(&self) -> ab_glyph::CodepointIdIter<'_> {
+			unimplemented!("not used by the renderer")
+		}
+
+		fn glyph_raster_image2(&self, _: GlyphId, _: u16) -> Option<ab_glyph::v2::GlyphImage<'_>> {
+			None
+		}
+
+		fn font_data(&self) -> &[u8] {
+			&[]
+		}
+	}
+
+	#[test]
+	fn glyph_over_background() {
+		let fg = image::Rgba([250, 200, 10, 255]);
+		let bg = image::Rgba([20, 40, 60, 255]);
+
+		let mut grid = Grid::new(2, 1);
+		grid.set(0, 0, GridCell::new_full_color(' ', fg, bg));
+		grid.set(1, 0, GridCell::new_full_color('x', fg, bg));
+
+		let mut sequence = GridSequence::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::MIN, Px(20.0));
+		sequence.append(Frame::single(grid));
+
+		let img = CpuRenderer::new(BoxFont, sequence).unwrap().next().unwrap().img;
+		let cell_width = img.width() / 2;
+
+		// at 20px the box is 12 by 20 pixels, everything else is background
+		assert_eq!(img.pixels().filter(|&&px| px == fg).count(), 12 * 20);
+		assert!(img.enumerate_pixels().all(|(x, _, &px)| px == bg || (px == fg && x >= cell_width)));
+	}
+
+	// `qlerp` from `shader.wgsl`, line for line
+	fn qlerp(a: [u8; 4], b: [u8; 4], t: u32) -> [u8; 4] {
+		let wa = a[3] as u32 * t;
+		let wb = b[3] as u32 * (255 - t);
+		let w = wa + wb;
+
+		if w == 0 {
+			return [0; 4];
+		}
+
+		let c = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / w) as u8;
+		[c(0), c(1), c(2), (w / 255) as u8]
+	}
+
+	#[test]
+	fn blend_span_matches_qlerp() {
+		let colors = [
+			[0, 0, 0, 255],
+			[255, 255, 255, 255],
+			[230, 200, 40, 255],
+			[20, 30, 60, 128],
+			[255, 0, 127, 1],
+			[90, 180, 10, 254],
+			[12, 34, 56, 0],
+			[0, 0, 0, 0]
+		];
+		let coverage: Vec<u8> = (0..=u8::MAX).collect();
+
+		for fg in colors {
+			for bg in colors {
+				let mut out = vec![0; coverage.len() * 4];
+				blend_span(&mut out, &coverage, fg, bg);
+
+				for (px, &t) in out.chunks_exact(4).zip(&coverage) {
+					assert_eq!(px, qlerp(fg, bg, t as u32), "fg {fg:?} bg {bg:?} t {t}");
+				}
+			}
+		}
+	}
 }
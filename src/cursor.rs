@@ -0,0 +1,83 @@
+use crate::color::Color;
+
+/// how the cursor is drawn
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CursorShape {
+	/// fills the cell, drawing its text in the cell's background color
+	#[default]
+	Block,
+	/// an outline around the cell
+	HollowBlock,
+	/// a line along the left edge of the cell
+	Bar,
+	/// a line along the bottom of the cell
+	Underline
+}
+
+/// a grid's cursor, which is hidden by default
+///
+/// on a wide cell, the cursor covers both of its columns
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cursor {
+	pub x: usize,
+	pub y: usize,
+	pub visible: bool,
+	pub shape: CursorShape,
+	/// [`Color::Default`] is the palette's cursor color, without one
+	/// the cursor is drawn in the foreground color of the cell it's on
+	pub color: Color
+}
+
+impl Cursor {
+	/// a visible cursor at `(x, y)`
+	#[inline]
+	pub fn new(x: usize, y: usize, shape: CursorShape) -> Self {
+		Self {
+			x,
+			y,
+			visible: true,
+			shape,
+			color: Color::Default
+		}
+	}
+}
+
+// the cells a visible cursor covers and what it's drawn with
+#[cfg(any(feature = "cpu", feature = "gpu"))]
+pub(crate) struct CursorSpan {
+	pub x: usize,
+	pub y: usize,
+	pub columns: usize,
+	pub shape: CursorShape,
+	/// `None` draws the cursor in the covered cells' own colors
+	pub color: Option<image::Rgba<u8>>
+}
+
+impl CursorShape {
+	// matches the shape constants in the shaders
+	#[cfg(feature = "gpu")]
+	#[inline]
+	pub(crate) fn id(self) -> u32 {
+		match self {
+			Self::Block => 0,
+			Self::HollowBlock => 1,
+			Self::Bar => 2,
+			Self::Underline => 3
+		}
+	}
+
+	// whether the pixel at `(x, y)` of a `width` by `height` cursor is drawn in the cursor color,
+	// blocks are the exception as they swap colors rather than drawing over the cell
+	#[cfg(feature = "cpu")]
+	#[inline]
+	pub(crate) fn covers(self, x: usize, y: usize, width: usize, height: usize, thickness: usize) -> bool {
+		match self {
+			Self::Block => false,
+			Self::HollowBlock => x < thickness || y < thickness || x + thickness >= width || y + thickness >= height,
+			Self::Bar => x < thickness,
+			Self::Underline => y + thickness >= height
+		}
+	}
+}
@@ -0,0 +1,242 @@
+use core::fmt;
+
+#[cfg(feature = "video")]
+use gstreamer as gst;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+	/// the font can't convert a point size to pixels
+	InvalidFontSize,
+	/// the grid's dimensions differ from the sequence's
+	MismatchedGrid,
+	/// the position is outside the grid
+	OutOfBounds {
+		x: usize,
+		y: usize
+	},
+	/// every glyph in the atlas is used by the grid being rendered, so none can be evicted
+	AtlasFull,
+	/// video sources with different dimensions or framerates can't be joined
+	MismatchedSources,
+	/// there are no video sources to join
+	NoSources,
+	#[cfg(feature = "gpu")]
+	RequestAdapter(wgpu::RequestAdapterError),
+	#[cfg(feature = "gpu")]
+	RequestDevice(wgpu::RequestDeviceError),
+	/// the device's limit `name` is below what the renderer needs
+	#[cfg(feature = "gpu")]
+	InsufficientLimit {
+		name: &'static str,
+		required: u64,
+		supported: u64
+	},
+	/// the texture's format, usage or size doesn't allow rendering into it
+	#[cfg(feature = "gpu")]
+	IncompatibleTexture,
+	#[cfg(feature = "gpu")]
+	BufferMap(wgpu::BufferAsyncError),
+	#[cfg(feature = "gpu")]
+	Poll(wgpu::PollError),
+	#[cfg(feature = "themes")]
+	Toml(toml::de::Error),
+	#[cfg(feature = "themes")]
+	Json(serde_json::Error),
+	#[cfg(feature = "themes")]
+	Xml(roxmltree::Error),
+	/// the theme sets `key` to something that isn't a color
+	#[cfg(feature = "themes")]
+	InvalidColor {
+		key: String,
+		value: String
+	},
+	#[cfg(feature = "container")]
+	Io(std::io::Error),
+	/// the file was written by a newer version of the `.dvd` format
+	#[cfg(feature = "container")]
+	UnsupportedVersion(u16),
+	/// the file isn't a `.dvd` file, or is corrupt
+	#[cfg(feature = "container")]
+	InvalidContainer(&'static str),
+	#[cfg(feature = "video")]
+	Glib(gst::glib::Error),
+	#[cfg(feature = "video")]
+	GlibBool(gst::glib::BoolError),
+	#[cfg(feature = "video")]
+	StateChange(gst::StateChangeError),
+	#[cfg(feature = "video")]
+	Flow(gst::FlowError),
+	/// the pipeline has no message bus
+	#[cfg(feature = "video")]
+	NoBus
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::InvalidFontSize => f.write_str("font size can't be resolved to pixels"),
+			Self::MismatchedGrid => f.write_str("grid dimensions differ from the sequence"),
+			Self::OutOfBounds { x, y } => write!(f, "position ({x}, {y}) is outside the grid"),
+			Self::AtlasFull => f.write_str("glyph atlas is full"),
+			Self::MismatchedSources => f.write_str("video sources have different dimensions or framerates"),
+			Self::NoSources => f.write_str("no video sources given"),
+			#[cfg(feature = "gpu")]
+			Self::RequestAdapter(e) => write!(f, "failed to request adapter: {e}"),
+			#[cfg(feature = "gpu")]
+			Self::RequestDevice(e) => write!(f, "failed to request device: {e}"),
+			#[cfg(feature = "gpu")]
+			Self::InsufficientLimit { name, required, supported } => {
+				write!(f, "device limit `{name}` is {supported}, but {required} is required")
+			},
+			#[cfg(feature = "gpu")]
+			Self::IncompatibleTexture => f.write_str("texture can't be rendered into"),
+			#[cfg(feature = "gpu")]
+			Self::BufferMap(e) => write!(f, "failed to map buffer: {e}"),
+			#[cfg(feature = "gpu")]
+			Self::Poll(e) => write!(f, "failed to poll device: {e}"),
+			#[cfg(feature = "themes")]
+			Self::Toml(e) => write!(f, "invalid toml: {e}"),
+			#[cfg(feature = "themes")]
+			Self::Json(e) => write!(f, "invalid json: {e}"),
+			#[cfg(feature = "themes")]
+			Self::Xml(e) => write!(f, "invalid xml: {e}"),
+			#[cfg(feature = "themes")]
+			Self::InvalidColor { key, value } => write!(f, "`{key}` isn't a color: {value}"),
+			#[cfg(feature = "container")]
+			Self::Io(e) => write!(f, "io error: {e}"),
+			#[cfg(feature = "container")]
+			Self::UnsupportedVersion(version) => write!(f, "unsupported .dvd version {version}"),
+			#[cfg(feature = "container")]
+			Self::InvalidContainer(reason) => write!(f, "invalid .dvd file: {reason}"),
+			#[cfg(feature = "video")]
+			Self::Glib(e) => write!(f, "gstreamer error: {e}"),
+			#[cfg(feature = "video")]
+			Self::GlibBool(e) => write!(f, "gstreamer error: {e}"),
+			#[cfg(feature = "video")]
+			Self::StateChange(e) => write!(f, "pipeline state change failed: {e}"),
+			#[cfg(feature = "video")]
+			Self::Flow(e) => write!(f, "pipeline flow error: {e}"),
+			#[cfg(feature = "video")]
+			Self::NoBus => f.write_str("pipeline has no bus")
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			#[cfg(feature = "gpu")]
+			Self::RequestAdapter(e) => Some(e),
+			#[cfg(feature = "gpu")]
+			Self::RequestDevice(e) => Some(e),
+			#[cfg(feature = "gpu")]
+			Self::BufferMap(e) => Some(e),
+			#[cfg(feature = "gpu")]
+			Self::Poll(e) => Some(e),
+			#[cfg(feature = "themes")]
+			Self::Toml(e) => Some(e),
+			#[cfg(feature = "themes")]
+			Self::Json(e) => Some(e),
+			#[cfg(feature = "themes")]
+			Self::Xml(e) => Some(e),
+			#[cfg(feature = "container")]
+			Self::Io(e) => Some(e),
+			#[cfg(feature = "video")]
+			Self::Glib(e) => Some(e),
+			#[cfg(feature = "video")]
+			Self::GlibBool(e) => Some(e),
+			#[cfg(feature = "video")]
+			Self::StateChange(e) => Some(e),
+			#[cfg(feature = "video")]
+			Self::Flow(e) => Some(e),
+			_ => None
+		}
+	}
+}
+
+#[cfg(feature = "gpu")]
+impl From<wgpu::RequestAdapterError> for Error {
+	fn from(e: wgpu::RequestAdapterError) -> Self {
+		Self::RequestAdapter(e)
+	}
+}
+
+#[cfg(feature = "gpu")]
+impl From<wgpu::RequestDeviceError> for Error {
+	fn from(e: wgpu::RequestDeviceError) -> Self {
+		Self::RequestDevice(e)
+	}
+}
+
+#[cfg(feature = "gpu")]
+impl From<wgpu::BufferAsyncError> for Error {
+	fn from(e: wgpu::BufferAsyncError) -> Self {
+		Self::BufferMap(e)
+	}
+}
+
+#[cfg(feature = "gpu")]
+impl From<wgpu::PollError> for Error {
+	fn from(e: wgpu::PollError) -> Self {
+		Self::Poll(e)
+	}
+}
+
+#[cfg(feature = "themes")]
+impl From<toml::de::Error> for Error {
+	fn from(e: toml::de::Error) -> Self {
+		Self::Toml(e)
+	}
+}
+
+#[cfg(feature = "themes")]
+impl From<serde_json::Error> for Error {
+	fn from(e: serde_json::Error) -> Self {
+		Self::Json(e)
+	}
+}
+
+#[cfg(feature = "themes")]
+impl From<roxmltree::Error> for Error {
+	fn from(e: roxmltree::Error) -> Self {
+		Self::Xml(e)
+	}
+}
+
+#[cfg(feature = "container")]
+impl From<std::io::Error> for Error {
+	fn from(e: std::io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+#[cfg(feature = "video")]
+impl From<gst::glib::Error> for Error {
+	fn from(e: gst::glib::Error) -> Self {
+		Self::Glib(e)
+	}
+}
+
+#[cfg(feature = "video")]
+impl From<gst::glib::BoolError> for Error {
+	fn from(e: gst::glib::BoolError) -> Self {
+		Self::GlibBool(e)
+	}
+}
+
+#[cfg(feature = "video")]
+impl From<gst::StateChangeError> for Error {
+	fn from(e: gst::StateChangeError) -> Self {
+		Self::StateChange(e)
+	}
+}
+
+#[cfg(feature = "video")]
+impl From<gst::FlowError> for Error {
+	fn from(e: gst::FlowError) -> Self {
+		Self::Flow(e)
+	}
+}
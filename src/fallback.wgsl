@@ -0,0 +1,109 @@
+@group(0) @binding(0) var idx_grid: texture_2d<u32>;
+@group(0) @binding(1) var color_grid: texture_2d<u32>;
+@group(0) @binding(2) var<uniform> glyph_size: vec2<u32>;
+@group(0) @binding(3) var<uniform> cursor: Cursor;
+@group(1) @binding(0) var atlas: texture_2d<u32>;
+@group(1) @binding(1) var<uniform> atlas_cols: u32;
+
+// the cells the cursor covers and how it's drawn, `columns` is 0 when it isn't
+struct Cursor {
+	x: u32,
+	y: u32,
+	columns: u32,
+	shape: u32,
+	// packed like `color_grid`, only used if `has_color` is set
+	color: u32,
+	has_color: u32,
+	thickness: u32,
+	_pad: u32,
+}
+
+// a single triangle covering the whole viewport
+@vertex
+fn fullscreen(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+	let uv = vec2(f32((i << 1u) & 2u), f32(i & 2u));
+
+	return vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// the top left of glyph `aidx`, which are laid out in rows of `atlas_cols`
+fn slot_pos(aidx: u32) -> vec2<u32> {
+	return vec2(aidx % atlas_cols, aidx / atlas_cols) * glyph_size;
+}
+
+// same as `unpack4xU8`, which isn't available everywhere
+fn unpack_color(c: u32) -> vec4<u32> {
+	return (vec4(c) >> vec4(0u, 8u, 16u, 24u)) & vec4(0xffu);
+}
+
+// lerp between `a` and `b` from `t=0` to `t=255`, weighting each color by its alpha
+// so that a transparent color doesn't darken the other, for opaque colors this is a plain lerp
+fn qlerp(a: vec4<u32>, b: vec4<u32>, t: u32) -> vec4<u32> {
+	let wa = a.a * t;
+	let wb = b.a * (255 - t);
+	let w = wa + wb;
+
+	if (w == 0) {
+		return vec4(0u);
+	}
+
+	return vec4((a.rgb * wa + b.rgb * wb) / w, w / 255);
+}
+
+const BLOCK: u32 = 0u;
+const HOLLOW_BLOCK: u32 = 1u;
+const BAR: u32 = 2u;
+
+// the pixel at `rg_pos` of cell `grid_pos`, with the cursor drawn over it if it's there
+fn draw_cursor(
+	grid_pos: vec2<u32>,
+	rg_pos: vec2<u32>,
+	glyph_size: vec2<u32>,
+	cov: u32,
+	fg_col: vec4<u32>,
+	bg_col: vec4<u32>
+) -> vec4<u32> {
+	let covered = grid_pos.y == cursor.y && grid_pos.x >= cursor.x && grid_pos.x < cursor.x + cursor.columns;
+	if (!covered) {
+		return qlerp(fg_col, bg_col, cov);
+	}
+
+	let color = select(fg_col, unpack_color(cursor.color), cursor.has_color != 0u);
+
+	// blocks draw the text in the cell's background, over the cursor
+	if (cursor.shape == BLOCK) {
+		return qlerp(bg_col, color, cov);
+	}
+
+	let pos = vec2(((grid_pos.x - cursor.x) * glyph_size.x) + rg_pos.x, rg_pos.y);
+	let size = vec2(cursor.columns * glyph_size.x, glyph_size.y);
+	let t = cursor.thickness;
+
+	var edge: bool;
+	switch cursor.shape {
+		case HOLLOW_BLOCK: {
+			edge = pos.x < t || pos.y < t || pos.x + t >= size.x || pos.y + t >= size.y;
+		}
+		case BAR: {
+			edge = pos.x < t;
+		}
+		default: {
+			edge = pos.y + t >= size.y;
+		}
+	}
+
+	return select(qlerp(fg_col, bg_col, cov), color, edge);
+}
+
+@fragment
+fn sample_atlas(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<u32> {
+	let img_pos = vec2<u32>(pos.xy);
+	let grid_pos = img_pos / glyph_size;
+	let rg_pos = img_pos % glyph_size;
+
+	let aidx = textureLoad(idx_grid, grid_pos, 0).r;
+	let cov = textureLoad(atlas, slot_pos(aidx) + rg_pos, 0).r;
+	let cols = textureLoad(color_grid, grid_pos, 0).rg;
+
+	return draw_cursor(grid_pos, rg_pos, glyph_size, cov, unpack_color(cols.r), unpack_color(cols.g));
+}
@@ -0,0 +1,425 @@
+use crate::atlas::Atlas;
+use crate::error::{Error, Result};
+use crate::gpu_render::{FrameData, OUTPUT_FORMAT};
+
+// the smallest limits the fragment shader in `fallback.wgsl` can run with
+pub(crate) fn required_limits(output_width: u32, output_height: u32, map_buf_len: u64) -> wgpu::Limits {
+	wgpu::Limits {
+		max_buffer_size: map_buf_len,
+		max_texture_dimension_2d: output_width.max(output_height),
+		..wgpu::Limits::downlevel_webgl2_defaults()
+	}
+}
+
+/// draws frames with a fullscreen triangle, for devices without compute shaders or storage textures
+pub(crate) struct FragmentSampler {
+	idx_grid: wgpu::Texture,
+	color_grid: wgpu::Texture,
+	cursor: wgpu::Buffer,
+	atlas_tex: wgpu::Texture,
+	// glyphs per row of `atlas_tex`
+	atlas_cols: u32,
+	atlas_cols_uniform: wgpu::Buffer,
+	atlas_bind_group_layout: wgpu::BindGroupLayout,
+	atlas_bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+	bind_group: wgpu::BindGroup,
+	output_view: wgpu::TextureView,
+	output_width: u32,
+	output_height: u32,
+}
+
+fn grid_texture(
+	device: &wgpu::Device,
+	label: &str,
+	width: u32,
+	height: u32,
+	format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+	device.create_texture(&wgpu::TextureDescriptor {
+		label: Some(label),
+		size: wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format,
+		usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+		view_formats: &[],
+	})
+}
+
+fn write_grid_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, data: &[u8], bytes_per_cell: u32) {
+	queue.write_texture(
+		texture.as_image_copy(),
+		data,
+		wgpu::TexelCopyBufferLayout {
+			offset: 0,
+			bytes_per_row: Some(texture.width() * bytes_per_cell),
+			rows_per_image: None,
+		},
+		texture.size(),
+	);
+}
+
+fn texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+	wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::FRAGMENT,
+		ty: wgpu::BindingType::Texture {
+			sample_type: wgpu::TextureSampleType::Uint,
+			view_dimension: wgpu::TextureViewDimension::D2,
+			multisampled: false,
+		},
+		count: None,
+	}
+}
+
+fn uniform_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+	wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::FRAGMENT,
+		ty: wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Uniform,
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		},
+		count: None,
+	}
+}
+
+// how many glyphs wide and tall an atlas texture holding `slots` glyphs is,
+// its rows can only be as wide as the device's largest texture
+fn atlas_layout(device: &wgpu::Device, atlas: &Atlas, slots: u32) -> Result<(u32, u32)> {
+	let max_dim = device.limits().max_texture_dimension_2d;
+	let max_cols = (max_dim / atlas.font_width).max(1);
+	let max_rows = max_dim / atlas.font_height;
+
+	let cols = slots.clamp(1, max_cols);
+	let rows = slots.div_ceil(cols).max(1);
+
+	if rows > max_rows {
+		return Err(Error::InsufficientLimit {
+			name: "max_texture_dimension_2d",
+			required: rows as u64 * atlas.font_height as u64,
+			supported: max_dim as u64,
+		});
+	}
+
+	Ok((cols, rows))
+}
+
+impl FragmentSampler {
+	pub const TARGET_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT;
+
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		grid_width: u32,
+		grid_height: u32,
+		atlas: &Atlas,
+		output_img: &wgpu::Texture,
+	) -> Result<Self> {
+		// each cell's atlas index, then its foreground and background colors
+		let idx_grid = grid_texture(device, "idx_grid", grid_width, grid_height, wgpu::TextureFormat::R32Uint);
+		let color_grid = grid_texture(device, "color_grid", grid_width, grid_height, wgpu::TextureFormat::Rg32Uint);
+
+		let glyph_size_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("glyph_size_uniform"),
+			size: 8,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		queue.write_buffer(&glyph_size_uniform, 0, &atlas.font_width.to_ne_bytes());
+		queue.write_buffer(&glyph_size_uniform, 4, &atlas.font_height.to_ne_bytes());
+
+		let cursor = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("cursor_uniform"),
+			size: 32,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let atlas_cols_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("atlas_cols_uniform"),
+			size: 4,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("fallback.wgsl"));
+
+		let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("fallback_bind_group_layout"),
+			entries: &[
+				// idx_grid
+				texture_layout_entry(0),
+				// color_grid
+				texture_layout_entry(1),
+				// glyph_size
+				uniform_layout_entry(2),
+				// cursor
+				uniform_layout_entry(3),
+			],
+		});
+
+		// the atlas texture is replaced when it grows, so it gets its own bind group
+		let atlas_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("fallback_atlas_bind_group_layout"),
+			entries: &[
+				// atlas
+				texture_layout_entry(0),
+				// atlas_cols
+				uniform_layout_entry(1),
+			],
+		});
+
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("fallback_pipeline_layout"),
+			bind_group_layouts: &[&bind_group_layout, &atlas_bind_group_layout],
+			push_constant_ranges: &[],
+		});
+
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("fallback_pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("fullscreen"),
+				compilation_options: wgpu::PipelineCompilationOptions::default(),
+				buffers: &[],
+			},
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("sample_atlas"),
+				compilation_options: wgpu::PipelineCompilationOptions::default(),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: OUTPUT_FORMAT,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+			}),
+			multiview: None,
+			cache: None,
+		});
+
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("fallback_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&idx_grid.create_view(&Default::default())),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(&color_grid.create_view(&Default::default())),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: glyph_size_uniform.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: cursor.as_entire_binding(),
+				},
+			],
+		});
+
+		let output_view = output_img.create_view(&wgpu::TextureViewDescriptor {
+			label: Some("output_img_view"),
+			format: Some(OUTPUT_FORMAT),
+			dimension: Some(wgpu::TextureViewDimension::D2),
+			usage: Some(Self::TARGET_USAGE),
+			..Default::default()
+		});
+
+		let (atlas_cols, atlas_rows) = atlas_layout(device, atlas, atlas.glyph_count() as u32)?;
+		let atlas_tex = Self::create_atlas(device, queue, atlas, atlas_cols, atlas_rows, &atlas_cols_uniform);
+		let atlas_bind_group = Self::atlas_bind_group(device, &atlas_bind_group_layout, &atlas_tex, &atlas_cols_uniform);
+
+		Ok(Self {
+			idx_grid,
+			color_grid,
+			cursor,
+			atlas_tex,
+			atlas_cols,
+			atlas_cols_uniform,
+			atlas_bind_group_layout,
+			atlas_bind_group,
+			pipeline,
+			bind_group,
+			output_view,
+			output_width: output_img.width(),
+			output_height: output_img.height(),
+		})
+	}
+
+	// a texture big enough for `cols` by `rows` glyphs, with the whole atlas written to it
+	fn create_atlas(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		atlas: &Atlas,
+		cols: u32,
+		rows: u32,
+		atlas_cols_uniform: &wgpu::Buffer,
+	) -> wgpu::Texture {
+		let atlas_tex = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("atlas"),
+			size: wgpu::Extent3d {
+				width: cols * atlas.font_width,
+				height: rows * atlas.font_height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::R8Uint,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+
+		queue.write_buffer(atlas_cols_uniform, 0, &cols.to_ne_bytes());
+		for slot in 0..atlas.glyph_count() as u32 {
+			Self::write_slot(queue, &atlas_tex, atlas, cols, slot);
+		}
+
+		atlas_tex
+	}
+
+	fn atlas_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		atlas_tex: &wgpu::Texture,
+		atlas_cols_uniform: &wgpu::Buffer,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("fallback_atlas_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&atlas_tex.create_view(&Default::default())),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: atlas_cols_uniform.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	// atlas slots are stored one after another, but drawn into the texture in rows of `cols`
+	fn write_slot(queue: &wgpu::Queue, atlas_tex: &wgpu::Texture, atlas: &Atlas, cols: u32, slot: u32) {
+		let slot_len = atlas.slot_len();
+		let start = slot as usize * slot_len;
+
+		queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture: atlas_tex,
+				mip_level: 0,
+				origin: wgpu::Origin3d {
+					x: (slot % cols) * atlas.font_width,
+					y: (slot / cols) * atlas.font_height,
+					z: 0,
+				},
+				aspect: wgpu::TextureAspect::All,
+			},
+			&atlas.buffer[start..start + slot_len],
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(atlas.font_width),
+				rows_per_image: None,
+			},
+			wgpu::Extent3d {
+				width: atlas.font_width,
+				height: atlas.font_height,
+				depth_or_array_layers: 1,
+			},
+		);
+	}
+
+	// uploads the `dirty` slots, the atlas texture is replaced if it's outgrown
+	pub fn sync_atlas(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		atlas: &Atlas,
+		dirty: &[u32],
+	) -> Result<()> {
+		let glyphs = atlas.glyph_count() as u32;
+		let capacity = self.atlas_cols * (self.atlas_tex.height() / atlas.font_height);
+
+		if glyphs > capacity {
+			// grow geometrically so that streaming in new glyphs doesn't reallocate every frame,
+			// falling back to exactly as many glyphs as are needed if that's too big
+			let (cols, rows) = atlas_layout(device, atlas, (capacity * 2).max(glyphs))
+				.or_else(|_| atlas_layout(device, atlas, glyphs))?;
+
+			self.atlas_cols = cols;
+			self.atlas_tex = Self::create_atlas(device, queue, atlas, cols, rows, &self.atlas_cols_uniform);
+			self.atlas_bind_group = Self::atlas_bind_group(
+				device,
+				&self.atlas_bind_group_layout,
+				&self.atlas_tex,
+				&self.atlas_cols_uniform,
+			);
+
+			return Ok(());
+		}
+
+		for &slot in dirty {
+			Self::write_slot(queue, &self.atlas_tex, atlas, self.atlas_cols, slot);
+		}
+
+		Ok(())
+	}
+
+	/// uploads a frame's cells and records drawing them into `target`, or the output image if `None`
+	pub fn encode(
+		&self,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		frame: &FrameData,
+		target: Option<&wgpu::TextureView>,
+	) {
+		write_grid_texture(queue, &self.idx_grid, &frame.idx_grid, 4);
+		write_grid_texture(queue, &self.color_grid, &frame.color_grid, 8);
+		queue.write_buffer(&self.cursor, 0, &frame.cursor);
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("sample_render_pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: target.unwrap_or(&self.output_view),
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// anything in `target` outside of the frame is left alone
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+			})],
+			depth_stencil_attachment: None,
+			timestamp_writes: None,
+			occlusion_query_set: None,
+		});
+		render_pass.set_viewport(
+			0.0,
+			0.0,
+			self.output_width as f32,
+			self.output_height as f32,
+			0.0,
+			1.0,
+		);
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
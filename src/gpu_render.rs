@@ -1,9 +1,15 @@
-use crate::atlas::populate_atlas;
+use crate::atlas::{Atlas, check_grid, populate_atlas};
+use crate::color::Palette;
+use crate::error::{Error, Result};
+use crate::gpu_fallback::{self, FragmentSampler};
+use crate::grid::Grid;
 use crate::render::{RenderedFrame, VideoSrc};
-use crate::sequence::GridSequence;
+use crate::sequence::{Frame, GridSequence};
 use ab_glyph::Font;
-use core::num::NonZeroU8;
-use std::collections::HashMap;
+use core::num::{NonZeroU8, NonZeroUsize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
 
 #[inline]
 fn compute_output_size(
@@ -19,32 +25,400 @@ fn compute_output_size(
 
 pub struct WgpuRenderer {
 	sequence: GridSequence,
-	lut: HashMap<char, u32>,
-	device: wgpu::Device,
+	atlas: Atlas,
+	pub(crate) device: wgpu::Device,
 	queue: wgpu::Queue,
-	idx_grid: wgpu::Buffer,
+	sampler: Sampler,
 	output_img: wgpu::Texture,
+	output_width: u32,
+	output_height: u32,
+	padded_bytes_width: u32,
+	staging: Vec<wgpu::Buffer>,
+	free_staging: Vec<usize>,
+	pub(crate) in_flight: VecDeque<InFlight>,
+	pub(crate) error: Option<Error>,
+}
+
+// a frame serialized for the samplers to upload
+pub(crate) struct FrameData {
+	pub idx_grid: Vec<u8>,
+	pub color_grid: Vec<u8>,
+	pub cursor: Vec<u8>,
+}
+
+// how frames are drawn, which depends on what the device supports
+enum Sampler {
+	Compute(ComputeSampler),
+	Fragment(FragmentSampler),
+}
+
+impl Sampler {
+	// what a texture needs to be usable as a render target
+	fn target_usage(&self) -> wgpu::TextureUsages {
+		match self {
+			Self::Compute(_) => ComputeSampler::TARGET_USAGE,
+			Self::Fragment(_) => FragmentSampler::TARGET_USAGE,
+		}
+	}
+}
+
+// draws frames with `sample_atlas` in `shader.wgsl`
+struct ComputeSampler {
+	atlas_buf: wgpu::Buffer,
+	atlas_bind_group_layout: wgpu::BindGroupLayout,
+	atlas_bind_group: wgpu::BindGroup,
+	idx_grid: wgpu::Buffer,
 	color_grid: wgpu::Buffer,
+	cursor: wgpu::Buffer,
 	pipeline: wgpu::ComputePipeline,
 	bind_group: wgpu::BindGroup,
+	output_bind_group_layout: wgpu::BindGroupLayout,
+	output_bind_group: wgpu::BindGroup,
 	output_width: u32,
 	output_height: u32,
 }
 
+// how many frames can be rendering or reading back at once
+const STAGING_BUFFERS: usize = 3;
+
+// filled in by the `map_async` callback, waking whoever is waiting on it
+#[derive(Default)]
+pub(crate) struct MapState {
+	pub result: Option<core::result::Result<(), wgpu::BufferAsyncError>>,
+	pub waker: Option<Waker>,
+}
+
+pub(crate) type MapSlot = Arc<Mutex<MapState>>;
+
+// a frame that's been submitted, and is waiting for its staging buffer to be mapped
+pub(crate) struct InFlight {
+	staging_idx: usize,
+	pub submission: wgpu::SubmissionIndex,
+	pub mapped: MapSlot,
+	#[cfg(feature = "stream")]
+	pub poll_requested: bool,
+	frame_hold: NonZeroU8,
+}
+
 fn round_up_aligned(n: u32) -> u32 {
 	use wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as ALIGN;
 
 	(ALIGN * (n / ALIGN)) + ALIGN
 }
 
+/// the format of the textures frames are rendered into
+pub const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Uint;
+
+fn atlas_bind_group(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	atlas_buf: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("atlas_bind_group"),
+		layout,
+		entries: &[wgpu::BindGroupEntry {
+			binding: 0,
+			resource: atlas_buf.as_entire_binding(),
+		}],
+	})
+}
+
+fn output_bind_group(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("output_bind_group"),
+		layout,
+		entries: &[wgpu::BindGroupEntry {
+			binding: 0,
+			resource: wgpu::BindingResource::TextureView(view),
+		}],
+	})
+}
+
+// the smallest limits `sample_atlas` can run with for a given grid and atlas
+fn required_limits(
+	grid_width: u32,
+	grid_height: u32,
+	atlas_len: u64,
+	output_width: u32,
+	output_height: u32,
+) -> wgpu::Limits {
+	let color_grid_len = grid_width as u64 * grid_height as u64 * 8;
+	let map_buf_len = round_up_aligned(output_width * 4) as u64 * output_height as u64;
+	let max_workgroups = int_div_round_up(output_width.max(output_height), 16);
+
+	wgpu::Limits {
+		max_buffer_size: map_buf_len.max(atlas_len).max(color_grid_len),
+		max_storage_buffer_binding_size: atlas_len.max(color_grid_len) as u32,
+		max_texture_dimension_2d: output_width.max(output_height),
+		max_storage_buffers_per_shader_stage: 3,
+		max_storage_textures_per_shader_stage: 1,
+		max_uniform_buffers_per_shader_stage: 5,
+		max_compute_workgroup_size_x: 16,
+		max_compute_workgroup_size_y: 16,
+		max_compute_workgroup_size_z: 1,
+		max_compute_invocations_per_workgroup: 16 * 16,
+		max_compute_workgroups_per_dimension: max_workgroups,
+		..wgpu::Limits::downlevel_webgl2_defaults()
+	}
+}
+
+// the compute shader needs compute support, a writable `OUTPUT_FORMAT` storage texture,
+// and limits to match, otherwise frames are drawn with the fragment shader fallback
+fn adapter_supports_compute(adapter: &wgpu::Adapter, compute_limits: &wgpu::Limits) -> bool {
+	let storage_output = adapter
+		.get_texture_format_features(OUTPUT_FORMAT)
+		.allowed_usages
+		.contains(wgpu::TextureUsages::STORAGE_BINDING);
+
+	adapter
+		.get_downlevel_capabilities()
+		.flags
+		.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+		&& storage_output
+		&& compute_limits.check_limits(&adapter.limits())
+}
+
+// errors out on the first limit that the renderer needs which the device doesn't meet
+fn check_device(device: &wgpu::Device, required_limits: &wgpu::Limits) -> Result<()> {
+	let mut insufficient = None;
+	required_limits.check_limits_with_fail_fn(&device.limits(), true, |name, required, supported| {
+		insufficient = Some(Error::InsufficientLimit {
+			name,
+			required,
+			supported,
+		});
+	});
+
+	match insufficient {
+		Some(e) => Err(e),
+		None => Ok(()),
+	}
+}
+
+/// how [`WgpuRenderer::with_options`] picks an adapter and creates its device
+#[derive(Clone, Debug)]
+pub struct WgpuRendererOptions {
+	backends: wgpu::Backends,
+	power_preference: wgpu::PowerPreference,
+	force_fallback_adapter: bool,
+	validation: bool,
+	memory_hints: wgpu::MemoryHints,
+	device_label: String,
+	adapter: Option<wgpu::Adapter>,
+}
+
+impl Default for WgpuRendererOptions {
+	fn default() -> Self {
+		Self {
+			backends: wgpu::Backends::all(),
+			power_preference: wgpu::PowerPreference::default(),
+			force_fallback_adapter: false,
+			validation: true,
+			memory_hints: wgpu::MemoryHints::Performance,
+			device_label: String::from("device"),
+			adapter: None,
+		}
+	}
+}
+
+impl WgpuRendererOptions {
+	#[inline]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// the backends adapters are chosen from, all of them by default
+	pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+		self.backends = backends;
+		self
+	}
+
+	pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+		self.power_preference = power_preference;
+		self
+	}
+
+	/// only pick a fallback adapter, which is generally a software implementation
+	pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+		self.force_fallback_adapter = force_fallback_adapter;
+		self
+	}
+
+	/// turn `wgpu`'s validation layers on or off, on by default
+	pub fn validation(mut self, validation: bool) -> Self {
+		self.validation = validation;
+		self
+	}
+
+	pub fn memory_hints(mut self, memory_hints: wgpu::MemoryHints) -> Self {
+		self.memory_hints = memory_hints;
+		self
+	}
+
+	pub fn device_label(mut self, device_label: impl Into<String>) -> Self {
+		self.device_label = device_label.into();
+		self
+	}
+
+	/// render on this adapter, such as one from [`WgpuRendererOptions::enumerate_adapters`],
+	/// rather than requesting one
+	pub fn adapter(mut self, adapter: wgpu::Adapter) -> Self {
+		self.adapter = Some(adapter);
+		self
+	}
+
+	/// every adapter on the chosen backends
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn enumerate_adapters(&self) -> Vec<wgpu::Adapter> {
+		self.instance().enumerate_adapters(self.backends)
+	}
+
+	fn instance(&self) -> wgpu::Instance {
+		let flags = match self.validation {
+			true => wgpu::InstanceFlags::VALIDATION,
+			false => wgpu::InstanceFlags::empty(),
+		};
+
+		wgpu::Instance::new(&wgpu::InstanceDescriptor {
+			backends: self.backends,
+			flags,
+			..Default::default()
+		})
+	}
+
+	async fn request_adapter(&self) -> Result<wgpu::Adapter> {
+		if let Some(adapter) = &self.adapter {
+			return Ok(adapter.clone());
+		}
+
+		let adapter = self
+			.instance()
+			.request_adapter(&wgpu::RequestAdapterOptions {
+				power_preference: self.power_preference,
+				force_fallback_adapter: self.force_fallback_adapter,
+				compatible_surface: None,
+			})
+			.await?;
+
+		Ok(adapter)
+	}
+}
+
 impl WgpuRenderer {
-	pub async fn new<F: Font>(font: F, sequence: GridSequence) -> Self {
+	/// create a renderer with its own `wgpu` device
+	///
+	/// adapters without compute shaders or storage textures, such as WebGL2,
+	/// draw frames with a fragment shader instead
+	pub async fn new<F: Font + Send + Sync + 'static>(font: F, sequence: GridSequence) -> Result<Self> {
+		Self::with_options(font, sequence, WgpuRendererOptions::default()).await
+	}
+
+	/// like [`WgpuRenderer::new`], but the adapter and device are created as `options` describe
+	pub async fn with_options<F: Font + Send + Sync + 'static>(
+		font: F,
+		sequence: GridSequence,
+		options: WgpuRendererOptions,
+	) -> Result<Self> {
+		let populated_atlas = populate_atlas(font, &sequence)?;
+		let adapter = options.request_adapter().await?;
+
+		let compute_limits = Self::required_limits(&sequence, &populated_atlas, true);
+		let compute = adapter_supports_compute(&adapter, &compute_limits);
+
+		// leave the atlas room to grow into
+		let adapter_limits = adapter.limits();
+		let required_limits = if compute {
+			wgpu::Limits {
+				max_buffer_size: adapter_limits.max_buffer_size.max(compute_limits.max_buffer_size),
+				max_storage_buffer_binding_size: adapter_limits
+					.max_storage_buffer_binding_size
+					.max(compute_limits.max_storage_buffer_binding_size),
+				..compute_limits
+			}
+		} else {
+			let fragment_limits = Self::required_limits(&sequence, &populated_atlas, false);
+
+			wgpu::Limits {
+				max_texture_dimension_2d: adapter_limits
+					.max_texture_dimension_2d
+					.max(fragment_limits.max_texture_dimension_2d),
+				..fragment_limits
+			}
+		};
+
+		let (device, queue) = adapter
+			.request_device(&wgpu::DeviceDescriptor {
+				required_features: wgpu::Features::empty(),
+				required_limits,
+				memory_hints: options.memory_hints,
+				label: Some(&options.device_label),
+				trace: wgpu::Trace::Off,
+			})
+			.await?;
+
+		Self::from_atlas(sequence, populated_atlas, device, queue, compute)
+	}
+
+	/// create a renderer on an existing `wgpu` device, which may be shared with other renderers
+	///
+	/// devices without the limits for compute shaders and storage textures draw frames with
+	/// a fragment shader instead, and fail with [`Error::InsufficientLimit`] if they can't do that either
+	pub fn with_device<F: Font + Send + Sync + 'static>(
+		font: F,
+		sequence: GridSequence,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+	) -> Result<Self> {
+		let populated_atlas = populate_atlas(font, &sequence)?;
+
+		let compute = Self::required_limits(&sequence, &populated_atlas, true).check_limits(&device.limits());
+		if !compute {
+			check_device(device, &Self::required_limits(&sequence, &populated_atlas, false))?;
+		}
+
+		Self::from_atlas(sequence, populated_atlas, device.clone(), queue.clone(), compute)
+	}
+
+	fn required_limits(sequence: &GridSequence, populated_atlas: &Atlas, compute: bool) -> wgpu::Limits {
+		let (width, height) = sequence.get_dimensions();
+		let (width, height) = (width.get() as u32, height.get() as u32);
+		let (output_width, output_height) = compute_output_size(
+			width,
+			height,
+			populated_atlas.font_width,
+			populated_atlas.font_height,
+		);
+
+		if !compute {
+			let map_buf_len = round_up_aligned(output_width * 4) as u64 * output_height as u64;
+			return gpu_fallback::required_limits(output_width, output_height, map_buf_len);
+		}
+
+		required_limits(
+			width,
+			height,
+			populated_atlas.buffer.len() as u64,
+			output_width,
+			output_height,
+		)
+	}
+
+	fn from_atlas(
+		sequence: GridSequence,
+		populated_atlas: Atlas,
+		device: wgpu::Device,
+		queue: wgpu::Queue,
+		compute: bool,
+	) -> Result<Self> {
 		let (width, height) = sequence.get_dimensions();
 		let width = width.get() as u32; // Convert NonZero<usize> to u32
 		let height = height.get() as u32; // Convert NonZero<usize> to u32
 
-		let populated_atlas = populate_atlas(font, &sequence);
-
 		let (output_width, output_height) = compute_output_size(
 			width,
 			height,
@@ -52,33 +426,89 @@ impl WgpuRenderer {
 			populated_atlas.font_height,
 		);
 
-		let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-			backends: wgpu::Backends::all(),
-			flags: wgpu::InstanceFlags::VALIDATION,
-			..Default::default()
+		let output_img_size = wgpu::Extent3d {
+			width: output_width,
+			height: output_height,
+			depth_or_array_layers: 1,
+		};
+
+		let target_usage = match compute {
+			true => ComputeSampler::TARGET_USAGE,
+			false => FragmentSampler::TARGET_USAGE,
+		};
+
+		let output_img = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("output_img"),
+			size: output_img_size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: OUTPUT_FORMAT,
+			usage: target_usage | wgpu::TextureUsages::COPY_SRC,
+			view_formats: &[],
 		});
 
-		let adapter = instance
-			.request_adapter(&wgpu::RequestAdapterOptions::default())
-			.await
-			.unwrap();
-		// I expect that the size of the atlas should be bounded by the size of the output
-		let max_buf_size = round_up_aligned(output_width * 4) as u64 * output_height as u64;
+		let sampler = match compute {
+			true => Sampler::Compute(ComputeSampler::new(
+				&device,
+				&queue,
+				width,
+				height,
+				&populated_atlas,
+				&output_img,
+			)),
+			false => Sampler::Fragment(FragmentSampler::new(
+				&device,
+				&queue,
+				width,
+				height,
+				&populated_atlas,
+				&output_img,
+			)?),
+		};
 
-		let (device, queue) = adapter
-			.request_device(&wgpu::DeviceDescriptor {
-				required_features: wgpu::Features::SHADER_INT64, // Fixed typo
-				required_limits: wgpu::Limits {
-					max_buffer_size: max_buf_size,
-					max_texture_dimension_2d: output_width.max(output_height),
-					..wgpu::Limits::default()
-				},
-				memory_hints: wgpu::MemoryHints::Performance, // Fixed typo
-				label: Some("device"),
-				trace: wgpu::Trace::Off,
+		let padded_bytes_width = round_up_aligned(output_width * 4);
+		let staging = (0..STAGING_BUFFERS)
+			.map(|_| {
+				device.create_buffer(&wgpu::BufferDescriptor {
+					label: Some("map_buf"),
+					size: padded_bytes_width as u64 * output_height as u64,
+					usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+					mapped_at_creation: false,
+				})
 			})
-			.await
-			.unwrap();
+			.collect();
+
+		Ok(Self {
+			sequence,
+			atlas: populated_atlas,
+			device,
+			queue,
+			sampler,
+			output_img,
+			output_width,
+			output_height,
+			padded_bytes_width,
+			staging,
+			free_staging: (0..STAGING_BUFFERS).rev().collect(),
+			in_flight: VecDeque::new(),
+			error: None,
+		})
+	}
+}
+
+impl ComputeSampler {
+	const TARGET_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::STORAGE_BINDING;
+
+	fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		width: u32,
+		height: u32,
+		populated_atlas: &Atlas,
+		output_img: &wgpu::Texture,
+	) -> Self {
+		let (output_width, output_height) = (output_img.width(), output_img.height());
 
 		let idx_grid = device.create_buffer(&wgpu::BufferDescriptor {
 			label: Some("idx_grid"),
@@ -87,32 +517,16 @@ impl WgpuRenderer {
 			mapped_at_creation: false,
 		});
 
-		let atlas = device.create_buffer(&wgpu::BufferDescriptor {
+		// a sequence without frames has nothing rasterised yet, but bindings can't be empty
+		let atlas_buf = device.create_buffer(&wgpu::BufferDescriptor {
 			label: Some("atlas"),
-			size: populated_atlas.buffer.len() as u64,
+			size: (populated_atlas.buffer.len() as u64).max(wgpu::COPY_BUFFER_ALIGNMENT),
 			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
 			mapped_at_creation: false,
 		});
 
 		// TODO: investigate efficiency of `write_buffer`
-		queue.write_buffer(&atlas, 0, &populated_atlas.buffer);
-
-		let output_img_size = wgpu::Extent3d {
-			width: output_width,
-			height: output_height,
-			depth_or_array_layers: 1,
-		};
-
-		let output_img = device.create_texture(&wgpu::TextureDescriptor {
-			label: Some("output_img"),
-			size: output_img_size,
-			mip_level_count: 1,
-			sample_count: 1,
-			dimension: wgpu::TextureDimension::D2,
-			format: wgpu::TextureFormat::Rgba8Uint,
-			usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-			view_formats: &[],
-		});
+		queue.write_buffer(&atlas_buf, 0, &populated_atlas.buffer);
 
 		let grid_width_uniform = device.create_buffer(&wgpu::BufferDescriptor {
 			label: Some("grid_width_uniform"),
@@ -155,6 +569,13 @@ impl WgpuRenderer {
 			mapped_at_creation: false,
 		});
 
+		let cursor = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("cursor_uniform"),
+			size: 32,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
 		let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
 		let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -171,31 +592,31 @@ impl WgpuRenderer {
 					},
 					count: None,
 				},
-				// atlas
+				// grid_width
 				wgpu::BindGroupLayoutEntry {
-					binding: 1,
+					binding: 3,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						ty: wgpu::BufferBindingType::Uniform,
 						has_dynamic_offset: false,
 						min_binding_size: None,
 					},
 					count: None,
 				},
-				// output_img
+				// grid_height
 				wgpu::BindGroupLayoutEntry {
-					binding: 2,
+					binding: 4,
 					visibility: wgpu::ShaderStages::COMPUTE,
-					ty: wgpu::BindingType::StorageTexture {
-						access: wgpu::StorageTextureAccess::WriteOnly, // Fixed typo
-						format: wgpu::TextureFormat::Rgba8Uint,
-						view_dimension: wgpu::TextureViewDimension::D2,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
 					},
 					count: None,
 				},
-				// grid_width
+				// img_width
 				wgpu::BindGroupLayoutEntry {
-					binding: 3,
+					binding: 5,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
 						ty: wgpu::BufferBindingType::Uniform,
@@ -204,9 +625,9 @@ impl WgpuRenderer {
 					},
 					count: None,
 				},
-				// grid_height
+				// img_height
 				wgpu::BindGroupLayoutEntry {
-					binding: 4,
+					binding: 6,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
 						ty: wgpu::BufferBindingType::Uniform,
@@ -215,20 +636,20 @@ impl WgpuRenderer {
 					},
 					count: None,
 				},
-				// img_width
+				// color_grid
 				wgpu::BindGroupLayoutEntry {
-					binding: 5,
+					binding: 7,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
 						has_dynamic_offset: false,
 						min_binding_size: None,
 					},
 					count: None,
 				},
-				// img_height
+				// cursor
 				wgpu::BindGroupLayoutEntry {
-					binding: 6,
+					binding: 8,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
 						ty: wgpu::BufferBindingType::Uniform,
@@ -237,9 +658,34 @@ impl WgpuRenderer {
 					},
 					count: None,
 				},
-				// color_grid
+			],
+		});
+
+		// the output lives in its own bind group, so that it can be swapped for a caller's texture
+		let output_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("output_bind_group_layout"),
+			entries: &[
+				// output_img
 				wgpu::BindGroupLayoutEntry {
-					binding: 7,
+					binding: 0,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::StorageTexture {
+						access: wgpu::StorageTextureAccess::WriteOnly,
+						format: OUTPUT_FORMAT,
+						view_dimension: wgpu::TextureViewDimension::D2,
+					},
+					count: None,
+				},
+			],
+		});
+
+		// the atlas has its own bind group as well, since its buffer is replaced when it grows
+		let atlas_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("atlas_bind_group_layout"),
+			entries: &[
+				// atlas
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
 					visibility: wgpu::ShaderStages::COMPUTE,
 					ty: wgpu::BindingType::Buffer {
 						ty: wgpu::BufferBindingType::Storage { read_only: true },
@@ -253,7 +699,11 @@ impl WgpuRenderer {
 
 		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
 			label: Some("pipeline_layout"),
-			bind_group_layouts: &[&bind_group_layout],
+			bind_group_layouts: &[
+				&bind_group_layout,
+				&output_bind_group_layout,
+				&atlas_bind_group_layout,
+			],
 			push_constant_ranges: &[],
 		});
 
@@ -274,26 +724,6 @@ impl WgpuRenderer {
 					binding: 0,
 					resource: idx_grid.as_entire_binding(),
 				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: atlas.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 2,
-					resource: wgpu::BindingResource::TextureView(&output_img.create_view(
-						&wgpu::TextureViewDescriptor {
-							label: Some("output_img_view"),
-							format: Some(wgpu::TextureFormat::Rgba8Uint),
-							dimension: Some(wgpu::TextureViewDimension::D2),
-							usage: Some(wgpu::TextureUsages::STORAGE_BINDING),
-							aspect: wgpu::TextureAspect::All,
-							base_mip_level: 0,
-							mip_level_count: None,
-							base_array_layer: 0,
-							array_layer_count: None,
-						},
-					)),
-				},
 				wgpu::BindGroupEntry {
 					binding: 3,
 					resource: grid_width_uniform.as_entire_binding(),
@@ -314,23 +744,127 @@ impl WgpuRenderer {
 					binding: 7,
 					resource: color_grid.as_entire_binding(),
 				},
+				wgpu::BindGroupEntry {
+					binding: 8,
+					resource: cursor.as_entire_binding(),
+				},
 			],
 		});
 
-		Self {
-			sequence,
-			lut: populated_atlas.lut,
+		let output_bind_group = output_bind_group(
 			device,
-			queue,
+			&output_bind_group_layout,
+			&output_img.create_view(&wgpu::TextureViewDescriptor {
+				label: Some("output_img_view"),
+				format: Some(OUTPUT_FORMAT),
+				dimension: Some(wgpu::TextureViewDimension::D2),
+				usage: Some(wgpu::TextureUsages::STORAGE_BINDING),
+				aspect: wgpu::TextureAspect::All,
+				base_mip_level: 0,
+				mip_level_count: None,
+				base_array_layer: 0,
+				array_layer_count: None,
+			}),
+		);
+
+		let atlas_bind_group = atlas_bind_group(device, &atlas_bind_group_layout, &atlas_buf);
+
+		Self {
+			atlas_buf,
+			atlas_bind_group_layout,
+			atlas_bind_group,
 			idx_grid,
-			output_img,
+			color_grid,
+			cursor,
 			pipeline,
 			bind_group,
+			output_bind_group_layout,
+			output_bind_group,
 			output_width,
 			output_height,
-			color_grid,
 		}
 	}
+
+	// uploads the `dirty` slots, the atlas buffer is replaced if it's outgrown
+	fn sync_atlas(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		atlas: &Atlas,
+		dirty: &[u32],
+	) -> Result<()> {
+		let atlas_len = atlas.buffer.len() as u64;
+		if atlas_len > self.atlas_buf.size() {
+			let limits = device.limits();
+			let max_len = limits
+				.max_buffer_size
+				.min(limits.max_storage_buffer_binding_size as u64);
+
+			if atlas_len > max_len {
+				return Err(Error::InsufficientLimit {
+					name: "max_storage_buffer_binding_size",
+					required: atlas_len,
+					supported: max_len,
+				});
+			}
+
+			// grow geometrically so that streaming in new glyphs doesn't reallocate every frame
+			self.atlas_buf = device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some("atlas"),
+				size: (self.atlas_buf.size() * 2).clamp(atlas_len, max_len),
+				usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+				mapped_at_creation: false,
+			});
+			self.atlas_bind_group = atlas_bind_group(device, &self.atlas_bind_group_layout, &self.atlas_buf);
+			queue.write_buffer(&self.atlas_buf, 0, &atlas.buffer);
+
+			return Ok(());
+		}
+
+		let slot_len = atlas.slot_len();
+		for &slot in dirty {
+			let start = slot as usize * slot_len;
+			queue.write_buffer(
+				&self.atlas_buf,
+				start as u64,
+				&atlas.buffer[start..start + slot_len],
+			);
+		}
+
+		Ok(())
+	}
+
+	// uploads a frame's cells and records the `sample_atlas` pass writing into `target`,
+	// or the output image if `None`
+	fn encode(
+		&self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		frame: &FrameData,
+		target: Option<&wgpu::TextureView>,
+	) {
+		queue.write_buffer(&self.color_grid, 0, &frame.color_grid);
+		queue.write_buffer(&self.idx_grid, 0, &frame.idx_grid);
+		queue.write_buffer(&self.cursor, 0, &frame.cursor);
+
+		let target_bind_group =
+			target.map(|view| output_bind_group(device, &self.output_bind_group_layout, view));
+
+		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("sample_compute_pass"),
+			timestamp_writes: None,
+		});
+		compute_pass.set_pipeline(&self.pipeline);
+		compute_pass.set_bind_group(0, &self.bind_group, &[]);
+		compute_pass.set_bind_group(1, target_bind_group.as_ref().unwrap_or(&self.output_bind_group), &[]);
+		compute_pass.set_bind_group(2, &self.atlas_bind_group, &[]);
+		compute_pass.dispatch_workgroups(
+			int_div_round_up(self.output_width, 16),
+			int_div_round_up(self.output_height, 16),
+			1,
+		);
+	}
 }
 
 impl RenderedFrame {
@@ -369,41 +903,181 @@ impl Iterator for WgpuRenderer {
 	type Item = RenderedFrame;
 
 	fn next(&mut self) -> Option<Self::Item> {
-		let frame = self.sequence.pop()?;
+		match self.receive() {
+			Ok(rendered) => rendered,
+			Err(e) => {
+				// ends iteration, the error can be retrieved with `VideoSrc::take_error`
+				self.error.get_or_insert(e);
+				self.abandon_in_flight();
+				None
+			}
+		}
+	}
+}
+
+impl WgpuRenderer {
+	/// the size in pixels of every rendered frame
+	#[inline]
+	pub fn output_size(&self) -> wgpu::Extent3d {
+		wgpu::Extent3d {
+			width: self.output_width,
+			height: self.output_height,
+			depth_or_array_layers: 1,
+		}
+	}
+
+	/// the format of textures passed to [`WgpuRenderer::render_next_into`]
+	#[inline]
+	pub fn output_format(&self) -> wgpu::TextureFormat {
+		OUTPUT_FORMAT
+	}
+
+	/// render the next frame straight into `target` without reading it back,
+	/// returning how long the frame is held for
+	///
+	/// `target` must have [`OUTPUT_FORMAT`], [`WgpuRenderer::target_usage`],
+	/// and be at least [`WgpuRenderer::output_size`] big
+	pub fn render_next_into(&mut self, target: &wgpu::Texture) -> Result<Option<NonZeroU8>> {
+		if target.format() != OUTPUT_FORMAT
+			|| !target.usage().contains(self.target_usage())
+			|| target.dimension() != wgpu::TextureDimension::D2
+			|| target.width() < self.output_width
+			|| target.height() < self.output_height
+		{
+			return Err(Error::IncompatibleTexture);
+		}
+
+		let view = target.create_view(&wgpu::TextureViewDescriptor {
+			label: Some("target_view"),
+			format: Some(OUTPUT_FORMAT),
+			dimension: Some(wgpu::TextureViewDimension::D2),
+			usage: Some(self.target_usage()),
+			mip_level_count: Some(1),
+			array_layer_count: Some(1),
+			..Default::default()
+		});
+
+		self.render_next_into_view(&view)
+	}
+
+	/// like [`WgpuRenderer::render_next_into`], but the view's texture isn't validated up front
+	pub fn render_next_into_view(&mut self, target: &wgpu::TextureView) -> Result<Option<NonZeroU8>> {
+		let Some(frame) = self.sequence.pop() else {
+			return Ok(None);
+		};
+
+		check_grid(&self.sequence, frame.grid())?;
+		self.sync_atlas(frame.grid())?;
+
+		let encoder = self.encode_frame(&frame, Some(target));
+		self.queue.submit(std::iter::once(encoder.finish()));
+
+		Ok(Some(frame.frame_hold))
+	}
+
+	/// the usage textures passed to [`WgpuRenderer::render_next_into`] need,
+	/// `STORAGE_BINDING` unless the fragment shader fallback is in use
+	#[inline]
+	pub fn target_usage(&self) -> wgpu::TextureUsages {
+		self.sampler.target_usage()
+	}
+
+	/// whether frames are drawn with the fragment shader fallback, because the device
+	/// lacks compute shaders or storage textures
+	#[inline]
+	pub fn is_fallback(&self) -> bool {
+		matches!(self.sampler, Sampler::Fragment(_))
+	}
+
+	/// cap the number of glyphs kept rasterised, evicting the least recently used ones beyond it
+	#[inline]
+	pub fn set_max_glyphs(&mut self, max_glyphs: Option<NonZeroUsize>) {
+		self.atlas.max_glyphs = max_glyphs;
+	}
 
+	/// the palette default and indexed colors are drawn with, replacing the sequence's
+	///
+	/// frames that are already rendering keep the palette they were submitted with
+	#[inline]
+	pub fn set_palette(&mut self, palette: Palette) {
+		self.sequence.palette = palette;
+	}
+
+	// rasterises any new glyphs in `grid` and uploads them
+	fn sync_atlas(&mut self, grid: &Grid) -> Result<()> {
+		self.atlas.prepare(grid)?;
+
+		let dirty = self.atlas.take_dirty();
+		if dirty.is_empty() {
+			return Ok(());
+		}
+
+		match &mut self.sampler {
+			Sampler::Compute(s) => s.sync_atlas(&self.device, &self.queue, &self.atlas, &dirty),
+			Sampler::Fragment(s) => s.sync_atlas(&self.device, &self.queue, &self.atlas, &dirty),
+		}
+	}
+
+	// uploads `frame` and records drawing it into `target`, or the output image if `None`
+	fn encode_frame(&self, frame: &Frame, target: Option<&wgpu::TextureView>) -> wgpu::CommandEncoder {
 		let mut encoder = self
 			.device
 			.create_command_encoder(&wgpu::CommandEncoderDescriptor {
 				label: Some("encoder"),
 			});
 
-		let frame_hold = frame.frame_hold;
-		self.queue
-			.write_buffer(&self.color_grid, 0, &frame.serialize_colors());
-		self.queue
-			.write_buffer(&self.idx_grid, 0, &frame.serialize(&self.lut));
+		let data = FrameData {
+			idx_grid: frame.serialize(&self.atlas.lut),
+			color_grid: frame.serialize_colors(&self.sequence.palette),
+			cursor: frame.serialize_cursor(&self.sequence.palette, self.atlas.cursor_thickness),
+		};
 
-		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-			label: Some("sample_compute_pass"),
-			timestamp_writes: None,
-		});
-		compute_pass.set_pipeline(&self.pipeline);
-		compute_pass.set_bind_group(0, &self.bind_group, &[]);
-		compute_pass.dispatch_workgroups(
-			int_div_round_up(self.output_width, 16),
-			int_div_round_up(self.output_height, 16),
-			1,
-		);
-		drop(compute_pass);
+		match &self.sampler {
+			Sampler::Compute(s) => s.encode(&self.device, &self.queue, &mut encoder, &data, target),
+			Sampler::Fragment(s) => s.encode(&self.queue, &mut encoder, &data, target),
+		}
 
-		let padded_bytes_width = round_up_aligned(self.output_width * 4);
-		let padded_bytes = padded_bytes_width * self.output_height;
-		let map_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
-			label: Some("map_buf"),
-			size: padded_bytes as u64,
-			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-			mapped_at_creation: false,
-		});
+		encoder
+	}
+
+	// keeps every free staging buffer busy with the upcoming frames
+	pub(crate) fn submit_pending(&mut self) -> Result<()> {
+		while let Some(staging_idx) = self.free_staging.pop() {
+			let Some(frame) = self.sequence.pop() else {
+				self.free_staging.push(staging_idx);
+				return Ok(());
+			};
+
+			let synced = check_grid(&self.sequence, frame.grid())
+				.and_then(|_| self.sync_atlas(frame.grid()));
+
+			if let Err(e) = synced {
+				self.free_staging.push(staging_idx);
+				return Err(e);
+			}
+
+			let (submission, mapped) = self.submit_readback(&frame, &self.staging[staging_idx]);
+
+			self.in_flight.push_back(InFlight {
+				staging_idx,
+				submission,
+				mapped,
+				#[cfg(feature = "stream")]
+				poll_requested: false,
+				frame_hold: frame.frame_hold,
+			});
+		}
+
+		Ok(())
+	}
+
+	// renders `frame` and queues up copying it into `map_buf`
+	fn submit_readback(
+		&self,
+		frame: &Frame,
+		map_buf: &wgpu::Buffer,
+	) -> (wgpu::SubmissionIndex, MapSlot) {
+		let mut encoder = self.encode_frame(frame, None);
 
 		encoder.copy_texture_to_buffer(
 			wgpu::TexelCopyTextureInfo {
@@ -413,34 +1087,130 @@ impl Iterator for WgpuRenderer {
 				aspect: wgpu::TextureAspect::All,
 			},
 			wgpu::TexelCopyBufferInfo {
-				buffer: &map_buf,
+				buffer: map_buf,
 				layout: wgpu::TexelCopyBufferLayout {
 					offset: 0,
-					bytes_per_row: Some(padded_bytes_width),
+					bytes_per_row: Some(self.padded_bytes_width),
 					rows_per_image: Some(self.output_height),
 				},
 			},
-			wgpu::Extent3d {
-				width: self.output_width,
-				height: self.output_height,
-				depth_or_array_layers: 1,
-			},
+			self.output_size(),
 		);
 
-		self.queue.submit(std::iter::once(encoder.finish()));
+		let submission = self.queue.submit(std::iter::once(encoder.finish()));
+
+		let mapped = MapSlot::default();
+		let callback_slot = Arc::clone(&mapped);
+		map_buf.map_async(wgpu::MapMode::Read, .., move |r| {
+			let mut state = callback_slot.lock().unwrap();
+			state.result = Some(r);
+
+			if let Some(waker) = state.waker.take() {
+				waker.wake();
+			}
+		});
+
+		(submission, mapped)
+	}
+
+	/// the frames that haven't been rendered yet
+	#[inline]
+	pub fn sequence(&self) -> &GridSequence {
+		&self.sequence
+	}
 
-		map_buf.map_async(wgpu::MapMode::Read, .., |r| r.unwrap());
-		self.device.poll(wgpu::PollType::Wait).unwrap();
+	/// render any frame, without consuming the sequence
+	pub fn render_frame(&mut self, frame: &Frame) -> Result<RenderedFrame> {
+		check_grid(&self.sequence, frame.grid())?;
+		self.sync_atlas(frame.grid())?;
+
+		let map_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("map_buf"),
+			size: self.padded_bytes_width as u64 * self.output_height as u64,
+			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+			mapped_at_creation: false,
+		});
+
+		let (submission, mapped) = self.submit_readback(frame, &map_buf);
+		self.device
+			.poll(wgpu::PollType::WaitForSubmissionIndex(submission))?;
+
+		let result = mapped.lock().unwrap().result.take();
+		result.expect("callback is invoked by `poll`")?;
 
 		let serialized_data = map_buf.get_mapped_range(..).to_vec();
-		Some(RenderedFrame::deserialize(
+		Ok(RenderedFrame::deserialize(
 			self.output_width,
 			self.output_height,
 			serialized_data,
-			frame_hold,
-			padded_bytes_width as usize,
+			frame.frame_hold,
+			self.padded_bytes_width as usize,
 		))
 	}
+
+	/// render a single grid, which is held for one frame
+	pub fn render_grid(&mut self, grid: &Grid) -> Result<RenderedFrame> {
+		self.render_frame(&Frame::single(grid.clone()))
+	}
+
+	// waits on the oldest frame in flight, queueing up more work before reading it back
+	//
+	// once there's an error, nothing more is queued, but the frames already in flight are still read back
+	fn receive(&mut self) -> Result<Option<RenderedFrame>> {
+		if self.error.is_none()
+			&& let Err(e) = self.submit_pending()
+		{
+			self.error = Some(e);
+		}
+
+		let Some(in_flight) = self.in_flight.front() else {
+			return Ok(None);
+		};
+
+		self.device
+			.poll(wgpu::PollType::WaitForSubmissionIndex(in_flight.submission.clone()))?;
+
+		self.finish_front()
+	}
+
+	// drops the frames in flight after one of them failed to read back, so that nothing more is returned
+	pub(crate) fn abandon_in_flight(&mut self) {
+		for in_flight in self.in_flight.drain(..) {
+			self.staging[in_flight.staging_idx].unmap();
+			self.free_staging.push(in_flight.staging_idx);
+		}
+	}
+
+	// reads back the oldest frame in flight, its staging buffer must have been mapped
+	pub(crate) fn finish_front(&mut self) -> Result<Option<RenderedFrame>> {
+		let Some(in_flight) = self.in_flight.pop_front() else {
+			return Ok(None);
+		};
+
+		let result = in_flight.mapped.lock().unwrap().result.take();
+		result.expect("callback is invoked by `poll`")?;
+
+		let map_buf = &self.staging[in_flight.staging_idx];
+		let serialized_data = map_buf.get_mapped_range(..).to_vec();
+		map_buf.unmap();
+		self.free_staging.push(in_flight.staging_idx);
+
+		// the gpu can get going on the next frame while this one is deserialized,
+		// a failure is reported once the frames already in flight are consumed
+		if self.error.is_none()
+			&& let Err(e) = self.submit_pending()
+		{
+			self.error = Some(e);
+		}
+
+		Ok(Some(RenderedFrame::deserialize(
+			self.output_width,
+			self.output_height,
+			serialized_data,
+			in_flight.frame_hold,
+			self.padded_bytes_width as usize,
+		)))
+	}
 }
 
 impl VideoSrc for WgpuRenderer {
@@ -458,4 +1228,9 @@ impl VideoSrc for WgpuRenderer {
 	fn height(&self) -> u32 {
 		self.output_height
 	}
+
+	#[inline]
+	fn take_error(&mut self) -> Option<Error> {
+		self.error.take()
+	}
 }
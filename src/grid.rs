@@ -1,39 +1,241 @@
-#[derive(Copy, Clone)]
+use crate::cluster::Cluster;
+use crate::color::{Color, Palette};
+use crate::cursor::Cursor;
+use crate::error::{Error, Result};
+use crate::writer::Writer;
+use core::fmt;
+use core::ops::{BitOr, BitOrAssign, Range};
+
+#[cfg(any(feature = "cpu", feature = "gpu"))]
+use crate::cursor::CursorSpan;
+
+/// how a cell's underline is drawn
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Underline {
+	#[default]
+	None,
+	Single,
+	Double,
+	Curly,
+	Dotted,
+	Dashed
+}
+
+/// SGR attributes of a cell, combined with `|`
+///
+/// the underline style is set with [`Attributes::with_underline`],
+/// when combining attributes the right hand side's underline wins if it has one
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Attributes(u16);
+
+impl Attributes {
+	pub const NONE: Self = Self(0);
+	pub const BOLD: Self = Self(1 << 0);
+	pub const ITALIC: Self = Self(1 << 1);
+	/// blends the foreground halfway toward the background
+	pub const DIM: Self = Self(1 << 2);
+	/// swaps the foreground and background
+	pub const REVERSE: Self = Self(1 << 3);
+	/// draws the foreground in the background color
+	pub const HIDDEN: Self = Self(1 << 4);
+	pub const STRIKETHROUGH: Self = Self(1 << 5);
+	pub const OVERLINE: Self = Self(1 << 6);
+
+	// the underline style is packed into the bits above the flags
+	const UNDERLINE_SHIFT: u16 = 8;
+	const UNDERLINE_MASK: u16 = 0b111 << Self::UNDERLINE_SHIFT;
+
+	// the attributes that change a glyph's coverage rather than its colors
+	const GLYPH_MASK: u16 = Self::BOLD.0
+		| Self::ITALIC.0
+		| Self::STRIKETHROUGH.0
+		| Self::OVERLINE.0
+		| Self::UNDERLINE_MASK;
+
+	#[inline]
+	pub const fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+
+	/// whether every flag in `other` is set, ignoring the underline style
+	#[inline]
+	pub const fn contains(self, other: Self) -> bool {
+		let flags = other.0 & !Self::UNDERLINE_MASK;
+		self.0 & flags == flags
+	}
+
+	#[inline]
+	pub fn insert(&mut self, other: Self) {
+		*self |= other;
+	}
+
+	/// clears every flag in `other`, and the underline if `other` has one
+	#[inline]
+	pub fn remove(&mut self, other: Self) {
+		let mut mask = other.0 & !Self::UNDERLINE_MASK;
+		if other.underline() != Underline::None {
+			mask |= Self::UNDERLINE_MASK;
+		}
+
+		self.0 &= !mask;
+	}
+
+	pub const fn underline(self) -> Underline {
+		match (self.0 & Self::UNDERLINE_MASK) >> Self::UNDERLINE_SHIFT {
+			1 => Underline::Single,
+			2 => Underline::Double,
+			3 => Underline::Curly,
+			4 => Underline::Dotted,
+			5 => Underline::Dashed,
+			_ => Underline::None
+		}
+	}
+
+	pub const fn with_underline(self, underline: Underline) -> Self {
+		let bits = match underline {
+			Underline::None => 0,
+			Underline::Single => 1,
+			Underline::Double => 2,
+			Underline::Curly => 3,
+			Underline::Dotted => 4,
+			Underline::Dashed => 5
+		};
+
+		Self((self.0 & !Self::UNDERLINE_MASK) | (bits << Self::UNDERLINE_SHIFT))
+	}
+
+	// how `.dvd` files store attributes
+	#[cfg(feature = "container")]
+	#[inline]
+	pub(crate) const fn bits(self) -> u16 {
+		self.0
+	}
+
+	#[cfg(feature = "container")]
+	#[inline]
+	pub(crate) const fn from_bits(bits: u16) -> Self {
+		Self(bits)
+	}
+
+	#[inline]
+	pub(crate) const fn glyph_attrs(self) -> Self {
+		Self(self.0 & Self::GLYPH_MASK)
+	}
+}
+
+impl BitOr for Attributes {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		let flags = Self((self.0 | rhs.0) & !Self::UNDERLINE_MASK);
+
+		match rhs.underline() {
+			Underline::None => flags.with_underline(self.underline()),
+			underline => flags.with_underline(underline)
+		}
+	}
+}
+
+impl BitOrAssign for Attributes {
+	#[inline]
+	fn bitor_assign(&mut self, rhs: Self) {
+		*self = *self | rhs;
+	}
+}
+
+// halfway between `a` and `b`
+#[inline]
+fn blend_half(a: image::Rgba<u8>, b: image::Rgba<u8>) -> image::Rgba<u8> {
+	image::Rgba(core::array::from_fn(|i| ((a.0[i] as u16 + b.0[i] as u16) / 2) as u8))
+}
+
+// whether a cell spans two columns, wide cells are followed by a continuation
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+enum Width {
+	Narrow,
+	Wide,
+	Continuation
+}
+
+// which part of a glyph a slot holds, wide glyphs are split across two slots
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Span {
+	Whole,
+	Left,
+	Right
+}
+
+// a cluster, and the attributes that change how it's rasterised
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct GlyphKey {
+	pub cluster: Cluster,
+	pub attrs: Attributes,
+	pub span: Span
+}
+
+impl Width {
+	#[inline]
+	fn of(cluster: Cluster) -> Self {
+		match cluster.width() {
+			2 => Self::Wide,
+			_ => Self::Narrow
+		}
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GridCell {
-	character: char,
-	fg_color: image::Rgba<u8>,
-	bg_color: image::Rgba<u8>
+	cluster: Cluster,
+	fg_color: Color,
+	bg_color: Color,
+	attrs: Attributes,
+	width: Width
 }
 
 impl GridCell {
 	#[inline]
-	pub fn new(character: char) -> Self {
+	pub fn new(character: impl Into<Cluster>) -> Self {
+		let cluster = character.into();
+
 		Self {
-			character,
-			fg_color: image::Rgba([u8::MAX, u8::MAX, u8::MAX, u8::MAX]),
-			bg_color: image::Rgba([u8::MIN, u8::MIN, u8::MIN, u8::MAX])
+			cluster,
+			fg_color: Color::Default,
+			bg_color: Color::Default,
+			attrs: Attributes::NONE,
+			width: Width::of(cluster)
 		}
 	}
 
 	#[inline]
-	pub fn new_fg_color(character: char, fg_color: image::Rgba<u8>) -> Self {
+	pub fn new_fg_color(character: impl Into<Cluster>, fg_color: impl Into<Color>) -> Self {
+		let cluster = character.into();
+
 		Self {
-			character,
-			fg_color,
-			bg_color: image::Rgba([u8::MIN, u8::MIN, u8::MIN, u8::MAX])
+			cluster,
+			fg_color: fg_color.into(),
+			bg_color: Color::Default,
+			attrs: Attributes::NONE,
+			width: Width::of(cluster)
 		}
 	}
 
 	#[inline]
 	pub fn new_full_color(
-		character: char,
-		fg_color: image::Rgba<u8>,
-		bg_color: image::Rgba<u8>
+		character: impl Into<Cluster>,
+		fg_color: impl Into<Color>,
+		bg_color: impl Into<Color>
 	) -> Self {
+		let cluster = character.into();
+
 		Self {
-			character,
-			fg_color,
-			bg_color
+			cluster,
+			fg_color: fg_color.into(),
+			bg_color: bg_color.into(),
+			attrs: Attributes::NONE,
+			width: Width::of(cluster)
 		}
 	}
 
@@ -43,74 +245,799 @@ impl GridCell {
 	}
 
 	#[inline]
-	pub fn set_fg_color(&mut self, fg_color: image::Rgba<u8>) {
-		self.fg_color = fg_color;
+	pub fn set_fg_color(&mut self, fg_color: impl Into<Color>) {
+		self.fg_color = fg_color.into();
+	}
+
+	#[inline]
+	pub fn set_bg_color(&mut self, bg_color: impl Into<Color>) {
+		self.bg_color = bg_color.into();
 	}
 
 	#[inline]
-	pub fn set_bg_color(&mut self, bg_color: image::Rgba<u8>) {
-		self.bg_color = bg_color;
+	pub fn set_attributes(&mut self, attrs: Attributes) {
+		self.attrs = attrs;
 	}
 
+	/// the first character of the cell's cluster
 	#[inline]
 	pub fn character(&self) -> char {
-		self.character
+		self.cluster.base()
+	}
+
+	#[inline]
+	pub fn cluster(&self) -> Cluster {
+		self.cluster
 	}
 
 	#[inline]
-	pub fn fg_color(&self) -> image::Rgba<u8> {
+	pub fn fg_color(&self) -> Color {
 		self.fg_color
 	}
 
 	#[inline]
-	pub fn bg_color(&self) -> image::Rgba<u8> {
+	pub fn bg_color(&self) -> Color {
 		self.bg_color
 	}
+
+	#[inline]
+	pub fn attributes(&self) -> Attributes {
+		self.attrs
+	}
+
+	/// whether the cluster is two columns wide, such as CJK and most emoji
+	#[inline]
+	pub fn is_wide(&self) -> bool {
+		self.width == Width::Wide
+	}
+
+	/// whether this is the second column of the wide cell to its left
+	#[inline]
+	pub fn is_continuation(&self) -> bool {
+		self.width == Width::Continuation
+	}
+
+	// how `.dvd` files store a cell's width, 2 being the continuation of a wide cell
+	#[cfg(feature = "container")]
+	#[inline]
+	pub(crate) fn width_code(&self) -> u8 {
+		match self.width {
+			Width::Narrow => 0,
+			Width::Wide => 1,
+			Width::Continuation => 2
+		}
+	}
+
+	#[cfg(feature = "container")]
+	#[inline]
+	pub(crate) fn with_width_code(self, code: u8) -> Option<Self> {
+		let width = match code {
+			0 => Width::Narrow,
+			1 => Width::Wide,
+			2 => Width::Continuation,
+			_ => return None
+		};
+
+		Some(Self { width, ..self })
+	}
+
+	// what's left of a wide cell once its other half is overwritten
+	#[inline]
+	fn blanked(self) -> Self {
+		Self {
+			cluster: Cluster::from(' '),
+			width: Width::Narrow,
+			..self
+		}
+	}
+
+	/// the foreground and background the cell is drawn with in `palette`,
+	/// once reverse, dim and hidden are applied
+	pub fn display_colors(&self, palette: &Palette) -> (image::Rgba<u8>, image::Rgba<u8>) {
+		let fg = palette.resolve_fg(self.fg_color);
+		let bg = palette.resolve_bg(self.bg_color);
+
+		let (mut fg, bg) = match self.attrs.contains(Attributes::REVERSE) {
+			true => (bg, fg),
+			false => (fg, bg)
+		};
+
+		if self.attrs.contains(Attributes::HIDDEN) {
+			fg = bg;
+		} else if self.attrs.contains(Attributes::DIM) {
+			fg = blend_half(fg, bg);
+		}
+
+		(fg, bg)
+	}
+
+	#[inline]
+	pub(crate) fn glyph_key(&self) -> GlyphKey {
+		let span = match self.width {
+			Width::Narrow => Span::Whole,
+			Width::Wide => Span::Left,
+			Width::Continuation => Span::Right
+		};
+
+		GlyphKey {
+			cluster: self.cluster,
+			attrs: self.attrs.glyph_attrs(),
+			span
+		}
+	}
+}
+
+/// a rectangle of cells, `x` and `y` are its top left corner
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+	pub x: usize,
+	pub y: usize,
+	pub width: usize,
+	pub height: usize
+}
+
+impl Rect {
+	#[inline]
+	pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+		Self { x, y, width, height }
+	}
+
+	// the columns and rows of the rect that lie within a `width` by `height` grid
+	#[inline]
+	fn clip(self, width: usize, height: usize) -> (Range<usize>, Range<usize>) {
+		let columns = self.x.min(width)..self.x.saturating_add(self.width).min(width);
+		let rows = self.y.min(height)..self.y.saturating_add(self.height).min(height);
+
+		(columns, rows)
+	}
 }
 
-#[derive(Clone)]
+/// a `width` by `height` grid of cells, stored row by row in a single allocation
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "GridData"))]
 pub struct Grid {
-	cells: Vec<Vec<GridCell>>
+	cells: Vec<GridCell>,
+	width: usize,
+	height: usize,
+	cursor: Cursor
 }
 
 impl Grid {
 	pub fn new(width: usize, height: usize) -> Self {
 		Self {
-			cells: vec![vec![GridCell::space(); width]; height]
+			cells: vec![GridCell::space(); width * height],
+			width,
+			height,
+			cursor: Cursor::default()
 		}
 	}
 
+	#[inline]
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	#[inline]
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	#[inline]
+	fn index(&self, x: usize, y: usize) -> Option<usize> {
+		(x < self.width && y < self.height).then_some((y * self.width) + x)
+	}
+
+	/// every cell, row by row
+	#[inline]
+	pub fn as_slice(&self) -> &[GridCell] {
+		&self.cells
+	}
+
+	/// `None` if out of bounds
+	#[inline]
+	pub fn row(&self, y: usize) -> Option<&[GridCell]> {
+		(y < self.height).then(|| &self.cells[y * self.width..(y + 1) * self.width])
+	}
+
+	pub fn rows(&self) -> impl ExactSizeIterator<Item = &[GridCell]> {
+		// a zero width grid has no cells to chunk, but `chunks_exact` can't take 0
+		self.cells.chunks_exact(self.width.max(1))
+	}
+
+	/// the cells in column `x` from top to bottom, empty if out of bounds
+	pub fn column(&self, x: usize) -> impl Iterator<Item = &GridCell> {
+		self.rows().filter_map(move |row| row.get(x))
+	}
+
+	/// `None` if out of bounds
+	#[inline]
+	pub fn get(&self, x: usize, y: usize) -> Option<&GridCell> {
+		self.index(x, y).map(|i| &self.cells[i])
+	}
+
+	/// `None` if out of bounds
+	#[inline]
+	pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut GridCell> {
+		self.index(x, y).map(|i| &mut self.cells[i])
+	}
+
 	/// panics if out of bounds
+	///
+	/// a wide cell also takes up the column to its right with a continuation,
+	/// and overwriting either half of a wide cell blanks the other half,
+	/// a continuation cell is set as a narrow one
 	pub fn set(&mut self, x: usize, y: usize, c: GridCell) {
-		self.cells[y][x] = c;
+		if let Err(e) = self.try_set(x, y, c) {
+			panic!("{e}");
+		}
+	}
+
+	/// like [`Grid::set`], but returns [`Error::OutOfBounds`] instead of panicking
+	pub fn try_set(&mut self, x: usize, y: usize, c: GridCell) -> Result<()> {
+		self.index(x, y).ok_or(Error::OutOfBounds { x, y })?;
+
+		let row = &mut self.cells[y * self.width..(y + 1) * self.width];
+
+		// a continuation copied out of a grid is only half of a cell, so it's set on its own
+		let c = match c.width {
+			Width::Continuation => GridCell { width: Width::Narrow, ..c },
+			_ => c
+		};
+
+		Self::break_wide(row, x);
+		row[x] = c;
+
+		if c.width == Width::Wide && x + 1 < row.len() {
+			Self::break_wide(row, x + 1);
+			row[x + 1] = GridCell {
+				width: Width::Continuation,
+				..c
+			};
+		}
+
+		Ok(())
+	}
+
+	// blanks the other half of a wide cell at `x`, if there is one
+	fn break_wide(row: &mut [GridCell], x: usize) {
+		match row[x].width {
+			Width::Narrow => (),
+			Width::Wide => {
+				if let Some(next) = row.get_mut(x + 1)
+					&& next.width == Width::Continuation
+				{
+					*next = next.blanked();
+				}
+			},
+			Width::Continuation => {
+				if let Some(prev) = x.checked_sub(1).and_then(|x| row.get_mut(x))
+					&& prev.width == Width::Wide
+				{
+					*prev = prev.blanked();
+				}
+			}
+		}
+	}
+
+	/// panics if out of bounds, the attributes of a wide cell apply to both of its columns
+	pub fn set_attributes(&mut self, x: usize, y: usize, attrs: Attributes) {
+		if let Err(e) = self.try_set_attributes(x, y, attrs) {
+			panic!("{e}");
+		}
+	}
+
+	/// like [`Grid::set_attributes`], but returns [`Error::OutOfBounds`] instead of panicking
+	pub fn try_set_attributes(&mut self, x: usize, y: usize, attrs: Attributes) -> Result<()> {
+		let i = self.index(x, y).ok_or(Error::OutOfBounds { x, y })?;
+
+		// the second column of a wide cell stands for the whole cell
+		let (x, i) = match self.cells[i].width {
+			Width::Continuation if x > 0 => (x - 1, i - 1),
+			_ => (x, i)
+		};
+
+		self.cells[i].attrs = attrs;
+
+		if self.cells[i].width == Width::Wide
+			&& x + 1 < self.width
+			&& self.cells[i + 1].width == Width::Continuation
+		{
+			self.cells[i + 1].attrs = attrs;
+		}
+
+		Ok(())
+	}
+
+	/// truncates or pads with spaces on the right and bottom, keeping cells at the same position
+	pub fn resize(&mut self, width: usize, height: usize) {
+		let mut cells = Vec::with_capacity(width * height);
+
+		for y in 0..height {
+			for x in 0..width {
+				let cell = self.get(x, y).copied().unwrap_or_else(GridCell::space);
+				cells.push(cell);
+			}
+		}
+
+		for row in cells.chunks_exact_mut(width.max(1)) {
+			// the right half of a wide cell may have been cut off
+			if width < self.width
+				&& let Some(last) = row.last_mut()
+				&& last.width == Width::Wide
+			{
+				*last = last.blanked();
+			}
+
+			// and a wide cell that was in the last column now has a blank after it
+			Self::mend_wide(row);
+		}
+
+		*self = Self {
+			cells,
+			width,
+			height,
+			cursor: self.cursor
+		};
+	}
+
+	#[inline]
+	pub fn cursor(&self) -> &Cursor {
+		&self.cursor
+	}
+
+	#[inline]
+	pub fn cursor_mut(&mut self) -> &mut Cursor {
+		&mut self.cursor
+	}
+
+	#[inline]
+	pub fn set_cursor(&mut self, cursor: Cursor) {
+		self.cursor = cursor;
+	}
+
+	// the cells the cursor covers, if it's visible and in bounds
+	#[cfg(any(feature = "cpu", feature = "gpu"))]
+	pub(crate) fn cursor_span(&self, palette: &Palette) -> Option<CursorSpan> {
+		let Cursor { x, y, visible, shape, color } = self.cursor;
+		let cell = self.get(x, y).filter(|_| visible)?;
+
+		let (x, columns) = match cell.width {
+			Width::Narrow => (x, 1),
+			Width::Wide if x + 1 < self.width => (x, 2),
+			Width::Wide => (x, 1),
+			Width::Continuation => x.checked_sub(1).map_or((x, 1), |x| (x, 2))
+		};
+
+		Some(CursorSpan {
+			x,
+			y,
+			columns,
+			shape,
+			color: palette.resolve_cursor(color)
+		})
+	}
+
+	/// a [`Writer`] with its cursor in the top left corner
+	#[inline]
+	pub fn writer(&mut self) -> Writer<'_> {
+		Writer::new(self)
+	}
+
+	/// sets every cell in `rect` to `c`, clipped to the grid
+	///
+	/// wide cells are repeated every other column, with a space wherever one doesn't fit
+	pub fn fill_rect(&mut self, rect: Rect, c: GridCell) {
+		let (columns, rows) = rect.clip(self.width, self.height);
+		let step = if c.width == Width::Wide { 2 } else { 1 };
+
+		for y in rows {
+			for x in columns.clone().step_by(step) {
+				match x + step <= columns.end {
+					true => self.set(x, y, c),
+					false => self.set(x, y, c.blanked())
+				}
+			}
+		}
+	}
+
+	/// resets every cell in `rect` to a space, clipped to the grid
+	#[inline]
+	pub fn clear_region(&mut self, rect: Rect) {
+		self.fill_rect(rect, GridCell::space());
+	}
+
+	/// resets row `y` to spaces, does nothing if out of bounds
+	#[inline]
+	pub fn clear_line(&mut self, y: usize) {
+		self.clear_region(Rect::new(0, y, self.width, 1));
+	}
+
+	/// moves the rows in `region` up by `n`, the rows scrolled in at the bottom are blank
+	///
+	/// `region` is clipped to the grid, the rows outside of it aren't touched
+	pub fn scroll_up(&mut self, region: Range<usize>, n: usize) {
+		let (start, end) = (region.start.min(self.height), region.end.min(self.height));
+		let region = start..end.max(start);
+		let n = n.min(region.len());
+		let (start, end) = (region.start * self.width, region.end * self.width);
+
+		self.cells.copy_within(start + (n * self.width)..end, start);
+		self.cells[end - (n * self.width)..end].fill(GridCell::space());
+	}
+
+	/// moves the rows in `region` down by `n`, the rows scrolled in at the top are blank
+	///
+	/// `region` is clipped to the grid, the rows outside of it aren't touched
+	pub fn scroll_down(&mut self, region: Range<usize>, n: usize) {
+		let (start, end) = (region.start.min(self.height), region.end.min(self.height));
+		let region = start..end.max(start);
+		let n = n.min(region.len());
+		let (start, end) = (region.start * self.width, region.end * self.width);
+
+		self.cells.copy_within(start..end - (n * self.width), start + (n * self.width));
+		self.cells[start..start + (n * self.width)].fill(GridCell::space());
+	}
+
+	/// copies the cells in `src_rect` of `src` into this grid with their top left corner at `(x, y)`,
+	/// clipped to both grids
+	///
+	/// wide cells split by either edge of the copy are blanked
+	pub fn blit(&mut self, src: &Grid, src_rect: Rect, x: usize, y: usize) {
+		let (columns, rows) = src_rect.clip(src.width, src.height);
+
+		let width = columns.len().min(self.width.saturating_sub(x));
+		let height = rows.len().min(self.height.saturating_sub(y));
+
+		if width == 0 || height == 0 {
+			return;
+		}
+
+		for dy in 0..height {
+			let src_start = ((rows.start + dy) * src.width) + columns.start;
+			let dst_start = ((y + dy) * self.width) + x;
+
+			self.cells[dst_start..dst_start + width]
+				.copy_from_slice(&src.cells[src_start..src_start + width]);
+
+			let row = &mut self.cells[(y + dy) * self.width..(y + dy + 1) * self.width];
+			Self::mend_wide(row);
+		}
+	}
+
+	// blanks the halves of wide cells that have lost their other half
+	fn mend_wide(row: &mut [GridCell]) {
+		for x in 0..row.len() {
+			let paired = |a: &GridCell, b: &GridCell| a.width == Width::Wide
+				&& b.width == Width::Continuation
+				&& a.cluster == b.cluster;
+
+			let orphaned = match row[x].width {
+				Width::Narrow => false,
+				// a wide cell in the last column has nowhere to put its continuation
+				Width::Wide => row.get(x + 1).is_some_and(|next| !paired(&row[x], next)),
+				Width::Continuation => x.checked_sub(1).is_none_or(|prev| !paired(&row[prev], &row[x]))
+			};
+
+			if orphaned {
+				row[x] = row[x].blanked();
+			}
+		}
+	}
+
+	// a grid from cells that were stored row by row, `None` if there are too few or too many,
+	// wide cells that lost their other half are blanked
+	#[cfg(any(feature = "serde", feature = "container"))]
+	pub(crate) fn from_cells(mut cells: Vec<GridCell>, width: usize, height: usize, cursor: Cursor) -> Option<Self> {
+		if Some(cells.len()) != width.checked_mul(height) {
+			return None;
+		}
+
+		for row in cells.chunks_exact_mut(width.max(1)) {
+			Self::mend_wide(row);
+		}
+
+		Some(Self {
+			cells,
+			width,
+			height,
+			cursor
+		})
+	}
+
+	/// the changes that turn this grid into `other`, see [`Grid::apply`]
+	pub fn diff(&self, other: &Grid) -> GridPatch {
+		let mut patch = GridPatch {
+			width: other.width,
+			height: other.height,
+			runs: Vec::new(),
+			cells: Vec::new(),
+			cursor: (self.cursor != other.cursor).then_some(other.cursor)
+		};
+
+		// there's nothing to line up cells against, so every cell is replaced
+		if self.width != other.width || self.height != other.height {
+			patch.runs.push((0, other.cells.len()));
+			patch.cells.extend_from_slice(&other.cells);
+			return patch;
+		}
+
+		let mut i = 0;
+		while i < other.cells.len() {
+			if self.cells[i] == other.cells[i] {
+				i += 1;
+				continue;
+			}
+
+			let start = i;
+			while i < other.cells.len() && self.cells[i] != other.cells[i] {
+				i += 1;
+			}
+
+			patch.runs.push((start, i - start));
+			patch.cells.extend_from_slice(&other.cells[start..i]);
+		}
+
+		patch
+	}
+
+	/// applies a patch from [`Grid::diff`], resizing the grid if the patch was made for another size
+	pub fn apply(&mut self, patch: &GridPatch) {
+		if self.width != patch.width || self.height != patch.height {
+			self.resize(patch.width, patch.height);
+		}
+
+		let mut cells = patch.cells.as_slice();
+		for &(start, len) in &patch.runs {
+			let (run, rest) = cells.split_at(len);
+			self.cells[start..start + len].copy_from_slice(run);
+			cells = rest;
+
+			// a patch that didn't come from `diff` can split a wide cell at either end of a run
+			if len > 0 {
+				let width = self.width;
+				let rows = start / width..(start + len).div_ceil(width);
+				for row in self.cells[rows.start * width..rows.end * width].chunks_exact_mut(width) {
+					Self::mend_wide(row);
+				}
+			}
+		}
+
+		if let Some(cursor) = patch.cursor {
+			self.cursor = cursor;
+		}
 	}
 
+	/// panics if out of bounds
 	pub fn get_cell(&self, x: usize, y: usize) -> &GridCell {
-		&self.cells[y][x]
+		self.get(x, y).expect("position out of bounds")
 	}
 
+	/// panics if out of bounds
 	pub fn get_cell_mut(&mut self, x: usize, y: usize) -> &mut GridCell {
-		&mut self.cells[y][x]
+		self.get_mut(x, y).expect("position out of bounds")
 	}
 
-	pub(crate) fn chars(&self) -> Vec<char> {
-		let mut chars = vec![];
+	/// the text of each row with trailing blanks trimmed, wide cells are written once
+	pub fn lines(&self) -> impl ExactSizeIterator<Item = String> + '_ {
+		self.rows().map(|row| {
+			let mut line: String = row.iter()
+				.filter(|cell| !cell.is_continuation())
+				.flat_map(|cell| cell.cluster.chars())
+				.collect();
 
-		for row in &self.cells {
-			for cell in row {
-				if !chars.contains(&cell.character) {
-					chars.push(cell.character);
-				}
+			line.truncate(line.trim_end().len());
+			line
+		})
+	}
+
+	pub(crate) fn clusters(&self) -> Vec<Cluster> {
+		let mut clusters = vec![];
+
+		for cell in &self.cells {
+			if !clusters.contains(&cell.cluster) {
+				clusters.push(cell.cluster);
+			}
+		}
+
+		clusters
+	}
+
+	pub(crate) fn glyph_keys(&self) -> Vec<GlyphKey> {
+		let mut keys = vec![];
+
+		for cell in &self.cells {
+			let key = cell.glyph_key();
+			if !keys.contains(&key) {
+				keys.push(key);
+			}
+		}
+
+		keys
+	}
+}
+
+// a grid as deserialized, before its cells are checked against its size
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GridData {
+	cells: Vec<GridCell>,
+	width: usize,
+	height: usize,
+	cursor: Cursor
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GridData> for Grid {
+	type Error = String;
+
+	fn try_from(data: GridData) -> core::result::Result<Self, String> {
+		let len = data.cells.len();
+
+		Self::from_cells(data.cells, data.width, data.height, data.cursor)
+			.ok_or_else(|| format!("{len} cells don't fill a {}x{} grid", data.width, data.height))
+	}
+}
+
+/// the grid's [`lines`](Grid::lines) joined by newlines, leaving out blank lines at the bottom
+impl fmt::Display for Grid {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let lines: Vec<String> = self.lines().collect();
+		let end = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |i| i + 1);
+
+		for (i, line) in lines[..end].iter().enumerate() {
+			if i > 0 {
+				f.write_str("\n")?;
 			}
+
+			f.write_str(line)?;
 		}
 
-		chars
+		Ok(())
 	}
+}
 
+/// the cells that changed between two grids, created by [`Grid::diff`]
+#[derive(Clone, PartialEq, Eq)]
+pub struct GridPatch {
+	pub(crate) width: usize,
+	pub(crate) height: usize,
+	// the start and length of every run of changed cells, with the cells in `cells`
+	pub(crate) runs: Vec<(usize, usize)>,
+	pub(crate) cells: Vec<GridCell>,
+	// the new cursor, if it changed
+	pub(crate) cursor: Option<Cursor>
+}
+
+impl GridPatch {
+	/// whether the grids were identical
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.runs.is_empty() && self.cursor.is_none()
+	}
+
+	/// the number of cells that changed
 	#[inline]
-	pub(crate) fn cells(&self) -> Vec<&[GridCell]> {
-		self.cells.iter()
-			.map(Vec::as_slice)
-			.collect()
+	pub fn len(&self) -> usize {
+		self.cells.len()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cursor::CursorShape;
+
+	#[test]
+	fn fill_rect_blanks_split_wide_cells() {
+		let mut grid = Grid::new(6, 1);
+		grid.set(0, 0, GridCell::new('漢'));
+		grid.set(4, 0, GridCell::new('字'));
+
+		// covers the right half of the first wide cell and the left half of the second
+		grid.fill_rect(Rect::new(1, 0, 4, 1), GridCell::new('x'));
+
+		let cells = grid.row(0).unwrap();
+		assert!(cells.iter().all(|cell| !cell.is_wide() && !cell.is_continuation()));
+		assert_eq!(cells.iter().map(|cell| cell.character()).collect::<String>(), " xxxx ");
+	}
+
+	#[test]
+	fn blit_blanks_split_wide_cells() {
+		let mut src = Grid::new(3, 1);
+		src.set(0, 0, GridCell::new('漢'));
+		src.set(2, 0, GridCell::new('y'));
+
+		let mut dst = Grid::new(4, 1);
+		dst.set(2, 0, GridCell::new('字'));
+
+		// the copy starts on the right half of `漢` and ends on the left half of `字`
+		dst.blit(&src, Rect::new(1, 0, 2, 1), 1, 0);
+
+		let cells = dst.row(0).unwrap();
+		assert!(cells.iter().all(|cell| !cell.is_wide() && !cell.is_continuation()));
+		assert_eq!(cells.iter().map(|cell| cell.character()).collect::<String>(), "  y ");
+	}
+
+	#[test]
+	fn diff_apply() {
+		let mut a = Grid::new(6, 3);
+		a.writer().write_str("hello\nworld");
+		let mut b = Grid::new(6, 3);
+		b.writer().write_str("help\nw漢rld");
+
+		for (from, to) in [(&a, &b), (&b, &a)] {
+			let mut patched = from.clone();
+			patched.apply(&from.diff(to));
+			assert!(patched == *to);
+		}
+
+		assert!(a.diff(&a).is_empty());
+		assert_eq!(a.diff(&b).len(), 7);
+	}
+
+	#[test]
+	fn diff_apply_resize() {
+		let mut a = Grid::new(6, 3);
+		a.writer().write_str("hello\nworld");
+		let mut b = Grid::new(4, 5);
+		b.writer().write_str("a\nb\ne\u{301}\n漢");
+
+		for (from, to) in [(&a, &b), (&b, &a), (&Grid::new(0, 0), &a)] {
+			let mut patched = from.clone();
+			patched.apply(&from.diff(to));
+			assert!(patched == *to);
+		}
+	}
+
+	#[test]
+	fn diff_apply_cursor() {
+		let mut a = Grid::new(4, 2);
+		a.writer().write_str("ab");
+		let mut b = a.clone();
+		b.set_cursor(Cursor::new(2, 1, CursorShape::Bar));
+
+		let patch = a.diff(&b);
+		assert!(!patch.is_empty());
+		assert_eq!(patch.len(), 0);
+
+		for (from, to) in [(&a, &b), (&b, &a)] {
+			let mut patched = from.clone();
+			patched.apply(&from.diff(to));
+			assert!(patched == *to);
+			assert_eq!(patched.cursor(), to.cursor());
+		}
+	}
+
+	#[test]
+	fn set_attributes_on_wide_cell() {
+		let mut grid = Grid::new(3, 1);
+		grid.set(0, 0, GridCell::new('漢'));
+
+		grid.set_attributes(1, 0, Attributes::BOLD);
+		assert_eq!(grid.get_cell(0, 0).attributes(), Attributes::BOLD);
+		assert_eq!(grid.get_cell(1, 0).attributes(), Attributes::BOLD);
+		assert_eq!(grid.get_cell(2, 0).attributes(), Attributes::default());
+
+		grid.set_attributes(0, 0, Attributes::ITALIC);
+		assert_eq!(grid.get_cell(1, 0).attributes(), Attributes::ITALIC);
+
+		assert!(matches!(grid.try_set_attributes(3, 0, Attributes::BOLD), Err(Error::OutOfBounds { x: 3, y: 0 })));
+	}
+
+	#[test]
+	fn resize_around_wide_cells() {
+		let mut grid = Grid::new(3, 2);
+		grid.set(2, 0, GridCell::new('漢'));
+		grid.set(0, 1, GridCell::new('字'));
+
+		grid.resize(5, 2);
+		assert_eq!(grid.get_cell(2, 0).character(), ' ');
+		assert!(!grid.get_cell(2, 0).is_wide());
+		assert!(grid.get_cell(0, 1).is_wide());
+		assert!(grid.get_cell(1, 1).is_continuation());
+
+		grid.resize(1, 2);
+		assert_eq!(grid.get_cell(0, 1).character(), ' ');
+		assert!(!grid.get_cell(0, 1).is_wide());
 	}
 }
@@ -1,19 +1,43 @@
 pub use ab_glyph;
 pub use image;
 
+pub mod error;
+pub use error::{Error, Result};
+
+pub mod cluster;
+pub mod color;
+pub mod cursor;
 pub mod grid;
+pub mod writer;
 pub mod sequence;
 pub mod render;
+pub mod search;
 
 pub mod prelude;
 
+#[cfg(any(feature = "cpu", feature = "gpu"))]
 mod atlas;
 
+#[cfg(any(feature = "cpu", feature = "gpu"))]
+mod shape;
+
 #[cfg(feature = "cpu")]
 pub mod cpu_render;
 
 #[cfg(feature = "gpu")]
 pub mod gpu_render;
 
+#[cfg(feature = "gpu")]
+mod gpu_fallback;
+
+#[cfg(feature = "stream")]
+pub mod stream;
+
+#[cfg(feature = "themes")]
+pub mod theme;
+
+#[cfg(feature = "container")]
+pub mod container;
+
 #[cfg(feature = "video")]
 pub mod video;
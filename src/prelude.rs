@@ -1,5 +1,18 @@
-pub use crate::grid::{Grid, GridCell};
+pub use crate::cluster::Cluster;
+pub use crate::color::{Color, Palette};
+pub use crate::cursor::{Cursor, CursorShape};
+pub use crate::error::Error;
+pub use crate::grid::{Attributes, Grid, GridCell, GridPatch, Rect, Underline};
+pub use crate::render::{IterSrc, RenderedFrame, VideoSrc};
+pub use crate::search::{FrameMatch, TextMatch};
 pub use crate::sequence::{Frame, GridSequence, Pt, Px};
+pub use crate::writer::Writer;
+
+#[cfg(feature = "cpu")]
+pub use crate::cpu_render::CpuRenderer;
 
 #[cfg(feature = "gpu")]
-pub use crate::gpu_render::WgpuRenderer;
+pub use crate::gpu_render::{WgpuRenderer, WgpuRendererOptions};
+
+#[cfg(feature = "container")]
+pub use crate::container::{DvdHeader, DvdReader, DvdWriter};
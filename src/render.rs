@@ -1,22 +1,293 @@
+use crate::error::{Error, Result};
 use core::num::NonZeroU8;
+use std::collections::VecDeque;
 
 pub struct RenderedFrame {
 	pub img: image::RgbaImage,
 	pub frame_hold: NonZeroU8,
 }
 
-mod private {
-	pub trait Sealed {}
+pub trait VideoSrc: Iterator<Item = RenderedFrame> + Send + 'static {
+	fn framerate(&self) -> NonZeroU8;
+	fn width(&self) -> u32;
+	fn height(&self) -> u32;
+
+	/// the error that ended iteration early, if any
+	fn take_error(&mut self) -> Option<Error> {
+		None
+	}
+
+	/// play `other` once this source is exhausted, fails if the dimensions or framerates differ
+	fn then<S: VideoSrc>(self, other: S) -> Result<Chain<Self, S>>
+	where
+		Self: Sized,
+	{
+		check_matching(&self, &other)?;
 
-	#[cfg(feature = "cpu")]
-	impl Sealed for crate::cpu_render::CpuRenderer {}
+		Ok(Chain {
+			first: Some(self),
+			second: other,
+			error: None,
+		})
+	}
 
-	#[cfg(feature = "gpu")]
-	impl Sealed for crate::gpu_render::WgpuRenderer {}
+	/// transform every frame, the image dimensions must be left unchanged
+	fn map_frames<F>(self, f: F) -> MapFrames<Self, F>
+	where
+		Self: Sized,
+		F: FnMut(RenderedFrame) -> RenderedFrame + Send + 'static,
+	{
+		MapFrames { src: self, f }
+	}
 }
 
-pub trait VideoSrc: Iterator<Item = RenderedFrame> + Send + 'static + private::Sealed {
-	fn framerate(&self) -> NonZeroU8;
-	fn width(&self) -> u32;
-	fn height(&self) -> u32;
+impl VideoSrc for Box<dyn VideoSrc> {
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		(**self).framerate()
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		(**self).width()
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		(**self).height()
+	}
+
+	#[inline]
+	fn take_error(&mut self) -> Option<Error> {
+		(**self).take_error()
+	}
+}
+
+fn check_matching<A: VideoSrc, B: VideoSrc>(a: &A, b: &B) -> Result<()> {
+	if a.width() != b.width() || a.height() != b.height() || a.framerate() != b.framerate() {
+		return Err(Error::MismatchedSources);
+	}
+
+	Ok(())
+}
+
+/// a video source backed by any iterator of frames,
+/// every frame must be `width` by `height` pixels
+pub struct IterSrc<I> {
+	iter: I,
+	framerate: NonZeroU8,
+	width: u32,
+	height: u32,
+}
+
+impl<I> IterSrc<I>
+where
+	I: Iterator<Item = RenderedFrame> + Send + 'static,
+{
+	pub fn new(iter: I, width: u32, height: u32, framerate: NonZeroU8) -> Self {
+		Self {
+			iter,
+			framerate,
+			width,
+			height,
+		}
+	}
+}
+
+impl<I: Iterator<Item = RenderedFrame>> Iterator for IterSrc<I> {
+	type Item = RenderedFrame;
+
+	#[inline]
+	fn next(&mut self) -> Option<Self::Item> {
+		self.iter.next()
+	}
+}
+
+impl<I> VideoSrc for IterSrc<I>
+where
+	I: Iterator<Item = RenderedFrame> + Send + 'static,
+{
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		self.framerate
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		self.width
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		self.height
+	}
+}
+
+/// created by [`VideoSrc::then`]
+pub struct Chain<A, B> {
+	first: Option<A>,
+	second: B,
+	error: Option<Error>,
+}
+
+impl<A: VideoSrc, B: VideoSrc> Iterator for Chain<A, B> {
+	type Item = RenderedFrame;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if let Some(first) = &mut self.first {
+			if let Some(frame) = first.next() {
+				return Some(frame);
+			}
+
+			self.error = first.take_error();
+			self.first = None;
+		}
+
+		// don't move on to the second source if the first one failed
+		if self.error.is_some() {
+			return None;
+		}
+
+		self.second.next()
+	}
+}
+
+impl<A: VideoSrc, B: VideoSrc> VideoSrc for Chain<A, B> {
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		self.second.framerate()
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		self.second.width()
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		self.second.height()
+	}
+
+	fn take_error(&mut self) -> Option<Error> {
+		self.error.take().or_else(|| self.second.take_error())
+	}
+}
+
+/// created by [`VideoSrc::map_frames`]
+pub struct MapFrames<S, F> {
+	src: S,
+	f: F,
+}
+
+impl<S, F> Iterator for MapFrames<S, F>
+where
+	S: VideoSrc,
+	F: FnMut(RenderedFrame) -> RenderedFrame,
+{
+	type Item = RenderedFrame;
+
+	#[inline]
+	fn next(&mut self) -> Option<Self::Item> {
+		self.src.next().map(&mut self.f)
+	}
+}
+
+impl<S, F> VideoSrc for MapFrames<S, F>
+where
+	S: VideoSrc,
+	F: FnMut(RenderedFrame) -> RenderedFrame + Send + 'static,
+{
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		self.src.framerate()
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		self.src.width()
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		self.src.height()
+	}
+
+	#[inline]
+	fn take_error(&mut self) -> Option<Error> {
+		self.src.take_error()
+	}
+}
+
+/// plays any number of sources back to back,
+/// use `Box<dyn VideoSrc>` to concatenate sources of different types
+pub struct Concat<S> {
+	sources: VecDeque<S>,
+	framerate: NonZeroU8,
+	width: u32,
+	height: u32,
+	error: Option<Error>,
+}
+
+impl<S: VideoSrc> Concat<S> {
+	/// fails if there are no sources, or if their dimensions or framerates differ
+	pub fn new(sources: impl IntoIterator<Item = S>) -> Result<Self> {
+		let sources: VecDeque<S> = sources.into_iter().collect();
+		let first = sources.front().ok_or(Error::NoSources)?;
+
+		for src in sources.iter().skip(1) {
+			check_matching(first, src)?;
+		}
+
+		Ok(Self {
+			framerate: first.framerate(),
+			width: first.width(),
+			height: first.height(),
+			sources,
+			error: None,
+		})
+	}
+}
+
+impl<S: VideoSrc> Iterator for Concat<S> {
+	type Item = RenderedFrame;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while let Some(src) = self.sources.front_mut() {
+			if let Some(frame) = src.next() {
+				return Some(frame);
+			}
+
+			if let Some(e) = src.take_error() {
+				self.error = Some(e);
+				self.sources.clear();
+				return None;
+			}
+
+			self.sources.pop_front();
+		}
+
+		None
+	}
+}
+
+impl<S: VideoSrc> VideoSrc for Concat<S> {
+	#[inline]
+	fn framerate(&self) -> NonZeroU8 {
+		self.framerate
+	}
+
+	#[inline]
+	fn width(&self) -> u32 {
+		self.width
+	}
+
+	#[inline]
+	fn height(&self) -> u32 {
+		self.height
+	}
+
+	#[inline]
+	fn take_error(&mut self) -> Option<Error> {
+		self.error.take()
+	}
 }
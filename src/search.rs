@@ -0,0 +1,194 @@
+use crate::grid::{Grid, GridCell};
+use crate::sequence::GridSequence;
+use core::time::Duration;
+
+/// a run of cells whose text matched a search, `columns` wide starting at `(x, y)`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextMatch {
+	pub x: usize,
+	pub y: usize,
+	pub columns: usize
+}
+
+/// a frame of a sequence with at least one match
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameMatch {
+	/// the frame's index in the sequence
+	pub index: usize,
+	/// when the frame is first shown
+	pub time: Duration,
+	pub matches: Vec<TextMatch>
+}
+
+// a row's text, with wide cells written once
+struct RowText {
+	text: String,
+	// the byte offset each cell's text starts at, with its column and width
+	cells: Vec<(usize, usize, usize)>
+}
+
+impl RowText {
+	fn new(row: &[GridCell]) -> Self {
+		let mut text = String::new();
+		let mut cells = Vec::with_capacity(row.len());
+
+		for (x, cell) in row.iter().enumerate().filter(|(_, cell)| !cell.is_continuation()) {
+			let width = if cell.is_wide() { 2 } else { 1 };
+
+			cells.push((text.len(), x, width));
+			text.extend(cell.cluster().chars());
+		}
+
+		Self { text, cells }
+	}
+
+	// the cells covering the bytes `start..end` of the text
+	fn cells_of(&self, y: usize, start: usize, end: usize) -> TextMatch {
+		let cell_at = |offset: usize| self.cells[self.cells.partition_point(|&(o, ..)| o <= offset) - 1];
+
+		let (_, x, _) = cell_at(start);
+		let (_, last_x, last_width) = cell_at(end - 1);
+
+		TextMatch {
+			x,
+			y,
+			columns: last_x + last_width - x
+		}
+	}
+}
+
+impl Grid {
+	/// the first place `text` appears, row by row
+	pub fn find(&self, text: &str) -> Option<TextMatch> {
+		self.find_with(|row| row.find(text).map(|i| (i, i + text.len())).into_iter().collect())
+			.next()
+	}
+
+	/// every place `text` appears, row by row, without overlapping
+	pub fn find_all(&self, text: &str) -> Vec<TextMatch> {
+		self.find_with(|row| row.match_indices(text).map(|(i, m)| (i, i + m.len())).collect())
+			.collect()
+	}
+
+	/// every match of `regex`, row by row
+	#[cfg(feature = "regex")]
+	pub fn find_regex(&self, regex: &regex::Regex) -> Vec<TextMatch> {
+		self.find_with(|row| regex.find_iter(row).map(|m| (m.start(), m.end())).collect())
+			.collect()
+	}
+
+	// the cells covered by the byte ranges `search` finds in each row's text,
+	// matches never span rows and empty ones are skipped
+	fn find_with<'a>(
+		&'a self,
+		mut search: impl FnMut(&str) -> Vec<(usize, usize)> + 'a
+	) -> impl Iterator<Item = TextMatch> + 'a {
+		self.rows().enumerate().flat_map(move |(y, row)| {
+			let row = RowText::new(row);
+
+			search(&row.text).into_iter()
+				.filter(|(start, end)| start < end)
+				.map(|(start, end)| row.cells_of(y, start, end))
+				.collect::<Vec<_>>()
+		})
+	}
+}
+
+impl GridSequence {
+	/// every frame `text` appears in, with where it appears
+	pub fn find<'a>(&'a self, text: &'a str) -> impl Iterator<Item = FrameMatch> + 'a {
+		self.find_with(move |grid| grid.find_all(text))
+	}
+
+	/// every frame with a match of `regex`, with where it matched
+	#[cfg(feature = "regex")]
+	pub fn find_regex<'a>(&'a self, regex: &'a regex::Regex) -> impl Iterator<Item = FrameMatch> + 'a {
+		self.find_with(move |grid| grid.find_regex(regex))
+	}
+
+	fn find_with<'a>(&'a self, search: impl Fn(&Grid) -> Vec<TextMatch> + 'a) -> impl Iterator<Item = FrameMatch> + 'a {
+		let mut ticks = 0;
+
+		self.iter().enumerate().filter_map(move |(index, frame)| {
+			let time = self.tick_time(ticks);
+			ticks += frame.frame_hold.get() as u64;
+
+			let matches = search(frame.grid());
+			(!matches.is_empty()).then_some(FrameMatch { index, time, matches })
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sequence::{Frame, Px};
+	use core::num::{NonZeroU8, NonZeroUsize};
+
+	#[test]
+	fn columns_of_wide_cells_and_clusters() {
+		let mut grid = Grid::new(8, 2);
+		grid.writer().write_str("a漢e\u{301}b字");
+
+		assert_eq!(grid.find("漢e\u{301}"), Some(TextMatch { x: 1, y: 0, columns: 3 }));
+		assert_eq!(grid.find("\u{301}b"), Some(TextMatch { x: 3, y: 0, columns: 2 }));
+		assert_eq!(grid.find("字"), Some(TextMatch { x: 5, y: 0, columns: 2 }));
+		assert_eq!(grid.find("ab"), None);
+		assert_eq!(grid.find_all("e"), vec![TextMatch { x: 3, y: 0, columns: 1 }]);
+	}
+
+	#[test]
+	fn trailing_blanks() {
+		let mut grid = Grid::new(6, 2);
+		grid.writer().write_str("ab");
+
+		assert_eq!(grid.find("b    "), Some(TextMatch { x: 1, y: 0, columns: 5 }));
+		assert_eq!(grid.find("b     "), None);
+		assert_eq!(grid.find_all("    "), vec![
+			TextMatch { x: 2, y: 0, columns: 4 },
+			TextMatch { x: 0, y: 1, columns: 4 }
+		]);
+
+		#[cfg(feature = "regex")]
+		assert_eq!(grid.find_regex(&regex::Regex::new(r"\S* +$").unwrap()), vec![
+			TextMatch { x: 0, y: 0, columns: 6 },
+			TextMatch { x: 0, y: 1, columns: 6 }
+		]);
+	}
+
+	#[test]
+	fn frame_matches() {
+		let mut sequence = GridSequence::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::MIN, Px(16.0));
+		sequence.framerate = NonZeroU8::new(4).unwrap();
+
+		for (text, frame_hold) in [("x", 1), ("y", 2), ("xx", 1), ("yx", 3)] {
+			let mut grid = Grid::new(4, 1);
+			grid.writer().write_str(text);
+			sequence.append(Frame::variable(grid, NonZeroU8::new(frame_hold).unwrap()));
+		}
+
+		let matches: Vec<_> = sequence.find("x").collect();
+
+		assert_eq!(matches, vec![
+			FrameMatch {
+				index: 0,
+				time: Duration::ZERO,
+				matches: vec![TextMatch { x: 0, y: 0, columns: 1 }]
+			},
+			FrameMatch {
+				index: 2,
+				time: Duration::from_millis(750),
+				matches: vec![TextMatch { x: 0, y: 0, columns: 1 }, TextMatch { x: 1, y: 0, columns: 1 }]
+			},
+			FrameMatch {
+				index: 3,
+				time: Duration::from_secs(1),
+				matches: vec![TextMatch { x: 1, y: 0, columns: 1 }]
+			}
+		]);
+
+		for m in &matches {
+			assert_eq!(sequence.start_time(m.index), Some(m.time));
+		}
+	}
+}
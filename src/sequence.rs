@@ -1,11 +1,24 @@
-use crate::grid::Grid;
+use crate::cluster::Cluster;
+use crate::color::Palette;
+use crate::grid::{GlyphKey, Grid, GridPatch};
 use core::num::NonZeroU8;
 use std::{
-	collections::{HashMap, HashSet, VecDeque},
+	collections::{HashSet, VecDeque},
 	num::NonZeroUsize,
+	time::Duration,
 };
 
+#[cfg(feature = "gpu")]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "cpu", feature = "gpu", feature = "serde"))]
+use crate::error::Error;
+
+#[cfg(any(feature = "cpu", feature = "gpu"))]
+use crate::error::Result;
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Frame {
 	grid: Grid,
 	pub frame_hold: NonZeroU8,
@@ -25,34 +38,62 @@ impl Frame {
 		Self { grid, frame_hold }
 	}
 
-	pub(crate) fn serialize(self, lut: &HashMap<char, u32>) -> Vec<u8> {
+	#[inline]
+	pub fn grid(&self) -> &Grid {
+		&self.grid
+	}
+
+	#[cfg(feature = "gpu")]
+	pub(crate) fn serialize(&self, lut: &HashMap<GlyphKey, u32>) -> Vec<u8> {
 		self.grid
-			.cells()
+			.as_slice()
 			.iter()
-			.flat_map(|row| {
-				row.iter()
-					.map(|cell| {
-						*lut.get(&cell.character())
-							.expect("invariant upheld by type system")
-					})
-					.flat_map(u32::to_ne_bytes)
+			.map(|cell| {
+				*lut.get(&cell.glyph_key())
+					.expect("invariant upheld by type system")
 			})
+			.flat_map(u32::to_ne_bytes)
 			.collect()
 	}
 
-	pub(crate) fn serialize_colors(&self) -> Vec<u8> {
+	#[cfg(feature = "gpu")]
+	pub(crate) fn serialize_colors(&self, palette: &Palette) -> Vec<u8> {
 		self.grid
-			.cells()
+			.as_slice()
 			.iter()
-			.flat_map(|row| {
-				row.iter()
-					.flat_map(|cell| [cell.fg_color().0, cell.bg_color().0])
-					.flatten()
+			.flat_map(|cell| {
+				let (fg, bg) = cell.display_colors(palette);
+				[fg.0, bg.0]
 			})
+			.flatten()
+			.collect()
+	}
+
+	// the `Cursor` uniform in the shaders, `columns` is 0 if the cursor isn't drawn
+	#[cfg(feature = "gpu")]
+	pub(crate) fn serialize_cursor(&self, palette: &Palette, thickness: u32) -> Vec<u8> {
+		let fields = match self.grid.cursor_span(palette) {
+			Some(cursor) => [
+				cursor.x as u32,
+				cursor.y as u32,
+				cursor.columns as u32,
+				cursor.shape.id(),
+				u32::from_ne_bytes(cursor.color.map_or([0; 4], |c| c.0)),
+				cursor.color.is_some() as u32,
+				thickness,
+				0,
+			],
+			None => [0; 8],
+		};
+
+		fields.into_iter()
+			.flat_map(u32::to_ne_bytes)
 			.collect()
 	}
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FontSize {
 	Pixel(f32),
 	PixelXY { x: f32, y: f32 },
@@ -93,11 +134,91 @@ impl From<Pt> for FontSize {
 	}
 }
 
+// a sequence is stored with every frame in full, so that its representation
+// doesn't depend on how often keyframes are taken
+#[cfg(feature = "serde")]
+impl serde::Serialize for GridSequence {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+		use serde::ser::SerializeStruct;
+
+		struct Frames<'a>(&'a GridSequence);
+
+		impl serde::Serialize for Frames<'_> {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+				serializer.collect_seq(self.0.iter())
+			}
+		}
+
+		let mut sequence = serializer.serialize_struct("GridSequence", 6)?;
+		sequence.serialize_field("width", &self.width)?;
+		sequence.serialize_field("height", &self.height)?;
+		sequence.serialize_field("framerate", &self.framerate)?;
+		sequence.serialize_field("font_scale", &self.font_scale)?;
+		sequence.serialize_field("palette", &self.palette)?;
+		sequence.serialize_field("frames", &Frames(self))?;
+		sequence.end()
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for GridSequence {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+		#[derive(serde::Deserialize)]
+		#[serde(rename = "GridSequence")]
+		struct Data {
+			width: NonZeroUsize,
+			height: NonZeroUsize,
+			framerate: NonZeroU8,
+			font_scale: FontSize,
+			palette: Palette,
+			frames: Vec<Frame>,
+		}
+
+		let data = Data::deserialize(deserializer)?;
+		let mut sequence = Self::new(data.width, data.height, data.font_scale);
+		sequence.framerate = data.framerate;
+		sequence.palette = data.palette;
+
+		for frame in data.frames {
+			if frame.grid().width() != data.width.get() || frame.grid().height() != data.height.get() {
+				return Err(serde::de::Error::custom(Error::MismatchedGrid));
+			}
+
+			sequence.append(frame);
+		}
+
+		Ok(sequence)
+	}
+}
+
+// frames are stored as patches on the frame before them, with a keyframe every
+// `KEYFRAME_INTERVAL` frames so that reconstructing any one of them stays cheap
+pub(crate) const KEYFRAME_INTERVAL: usize = 64;
+
+enum Stored {
+	Keyframe(Grid),
+	Patch(GridPatch),
+}
+
+struct Entry {
+	grid: Stored,
+	frame_hold: NonZeroU8,
+}
+
 pub struct GridSequence {
 	pub framerate: NonZeroU8,
-	frames: VecDeque<Frame>,
+	// the first entry is always a keyframe
+	frames: VecDeque<Entry>,
+	// the grid of the last frame, which appended frames are diffed against
+	tail: Option<Grid>,
+	// patches since the last keyframe at the end of the sequence
+	since_keyframe: usize,
 	pub font_scale: FontSize,
-	glyph_set: HashSet<char>,
+	/// what default and indexed colors are drawn as
+	pub palette: Palette,
+	glyph_set: HashSet<Cluster>,
+	// every cluster paired with the attributes that change its shape
+	glyph_keys: HashSet<GlyphKey>,
 	width: NonZeroUsize,
 	height: NonZeroUsize,
 }
@@ -107,8 +228,12 @@ impl GridSequence {
 		Self {
 			framerate: NonZeroU8::MIN,
 			frames: VecDeque::new(),
+			tail: None,
+			since_keyframe: 0,
 			font_scale: s.into(),
+			palette: Palette::default(),
 			glyph_set: HashSet::new(),
+			glyph_keys: HashSet::new(),
 			width,
 			height,
 		}
@@ -121,39 +246,298 @@ impl GridSequence {
 
 	/// push a frame to the beginning of the sequence
 	pub fn prepend(&mut self, frame: Frame) {
-		for c in frame.grid.chars() {
+		for c in frame.grid.clusters() {
 			self.glyph_set.insert(c);
 		}
 
-		self.frames.push_front(frame);
+		self.glyph_keys.extend(frame.grid.glyph_keys());
+
+		if self.frames.is_empty() {
+			self.tail = Some(frame.grid.clone());
+			self.since_keyframe = 0;
+		}
+
+		self.frames.push_front(Entry {
+			grid: Stored::Keyframe(frame.grid),
+			frame_hold: frame.frame_hold,
+		});
 	}
 
 	/// push a frame to the end of the sequence
 	pub fn append(&mut self, frame: Frame) {
-		for c in frame.grid.chars() {
+		for c in frame.grid.clusters() {
 			self.glyph_set.insert(c);
 		}
 
-		self.frames.push_back(frame);
+		self.glyph_keys.extend(frame.grid.glyph_keys());
+
+		let patch = self.tail.as_ref()
+			.filter(|_| !self.frames.is_empty() && self.since_keyframe < KEYFRAME_INTERVAL)
+			.map(|tail| tail.diff(&frame.grid))
+			// a patch replacing most of the grid saves next to nothing over a keyframe
+			.filter(|patch| patch.len() <= frame.grid.as_slice().len() / 2);
+
+		let grid = match patch {
+			Some(patch) => {
+				self.since_keyframe += 1;
+				Stored::Patch(patch)
+			},
+			None => {
+				self.since_keyframe = 0;
+				Stored::Keyframe(frame.grid.clone())
+			},
+		};
+
+		self.tail = Some(frame.grid);
+		self.frames.push_back(Entry {
+			grid,
+			frame_hold: frame.frame_hold,
+		});
 	}
 
+	/// the number of frames left in the sequence
 	#[inline]
-	pub fn glyph_set(&self) -> &HashSet<char> {
+	pub fn len(&self) -> usize {
+		self.frames.len()
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+
+	/// reconstructs the frame at `index` from the keyframe before it
+	pub fn get(&self, index: usize) -> Option<Frame> {
+		let entry = self.frames.get(index)?;
+		let keyframe = (0..=index).rev()
+			.find(|&i| matches!(self.frames[i].grid, Stored::Keyframe(_)))
+			.expect("the first frame is always a keyframe");
+
+		let mut grid = None;
+		for entry in self.frames.range(keyframe..=index) {
+			Self::advance(&mut grid, &entry.grid);
+		}
+
+		Some(Frame::variable(grid?, entry.frame_hold))
+	}
+
+	/// reconstructs every frame in order
+	pub fn iter(&self) -> impl Iterator<Item = Frame> {
+		let mut grid = None;
+
+		self.frames.iter().map(move |entry| {
+			Frame::variable(Self::advance(&mut grid, &entry.grid).clone(), entry.frame_hold)
+		})
+	}
+
+	// moves `grid` on to the frame stored as `stored`
+	fn advance<'a>(grid: &'a mut Option<Grid>, stored: &Stored) -> &'a mut Grid {
+		match stored {
+			Stored::Keyframe(keyframe) => grid.insert(keyframe.clone()),
+			Stored::Patch(patch) => {
+				let grid = grid.as_mut().expect("the first frame is always a keyframe");
+				grid.apply(patch);
+				grid
+			},
+		}
+	}
+
+	/// the time at which the frame at `index` is first shown
+	pub fn start_time(&self, index: usize) -> Option<Duration> {
+		if index >= self.frames.len() {
+			return None;
+		}
+
+		let ticks: u64 = self.frames.iter()
+			.take(index)
+			.map(|frame| frame.frame_hold.get() as u64)
+			.sum();
+
+		Some(self.tick_time(ticks))
+	}
+
+	/// the index of the frame being shown at `time`, or `None` if the sequence has ended by then
+	pub fn index_at(&self, time: Duration) -> Option<usize> {
+		// compared in units of 1/(framerate * 10^9) seconds, so that nothing is rounded
+		let time = time.as_nanos() * self.framerate.get() as u128;
+		let mut end = 0;
+
+		self.frames.iter().position(|frame| {
+			end += frame.frame_hold.get() as u128;
+			time < end * 1_000_000_000
+		})
+	}
+
+	// when a frame `ticks` frames into the sequence is first shown, rounded up to the
+	// first nanosecond `index_at` puts inside it
+	#[inline]
+	pub(crate) fn tick_time(&self, ticks: u64) -> Duration {
+		Duration::from_nanos((ticks * 1_000_000_000).div_ceil(self.framerate.get() as u64))
+	}
+
+	#[inline]
+	pub fn glyph_set(&self) -> &HashSet<Cluster> {
 		&self.glyph_set
 	}
 
+	// clusters the renderers should size cells for, ahead of the frames that use them
+	#[cfg(feature = "container")]
+	pub(crate) fn extend_glyph_set(&mut self, clusters: impl IntoIterator<Item = Cluster>) {
+		self.glyph_set.extend(clusters);
+	}
+
+	#[cfg(any(feature = "cpu", feature = "gpu"))]
 	#[inline]
+	pub(crate) fn glyph_keys(&self) -> &HashSet<GlyphKey> {
+		&self.glyph_keys
+	}
+
+	/// the first frame, the one after it becomes a keyframe
+	#[cfg(any(feature = "cpu", feature = "gpu", test))]
 	pub(crate) fn pop(&mut self) -> Option<Frame> {
-		self.frames.pop_front()
+		let entry = self.frames.pop_front()?;
+		let Stored::Keyframe(grid) = entry.grid else {
+			unreachable!("the first frame is always a keyframe");
+		};
+
+		if let Some(next) = self.frames.front_mut()
+			&& let Stored::Patch(patch) = &next.grid
+		{
+			let mut keyframe = grid.clone();
+			keyframe.apply(patch);
+			next.grid = Stored::Keyframe(keyframe);
+		}
+
+		if self.frames.is_empty() {
+			self.tail = None;
+		}
+
+		Some(Frame::variable(grid, entry.frame_hold))
 	}
 
-	pub(crate) fn resolve_px_scale<F: ab_glyph::Font>(&self, font: F) -> ab_glyph::PxScale {
+	#[cfg(any(feature = "cpu", feature = "gpu"))]
+	pub(crate) fn resolve_px_scale<F: ab_glyph::Font>(&self, font: F) -> Result<ab_glyph::PxScale> {
 		match self.font_scale {
-			FontSize::Pixel(s) => ab_glyph::PxScale::from(s),
-			FontSize::PixelXY { x, y } => ab_glyph::PxScale { x, y },
+			FontSize::Pixel(s) => Ok(ab_glyph::PxScale::from(s)),
+			FontSize::PixelXY { x, y } => Ok(ab_glyph::PxScale { x, y }),
+			// fails if the font doesn't specify its units per em
 			FontSize::Point(pt) => font
 				.pt_to_px_scale(pt)
-				.expect("not sure why this would fail?"),
+				.ok_or(Error::InvalidFontSize),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::grid::{GridCell, Rect};
+
+	#[test]
+	fn reconstructs_frames() {
+		// one cell changes every frame, and every 50th frame fills the whole grid so that it's a keyframe
+		let mut grid = Grid::new(8, 4);
+		let grids: Vec<Grid> = (0..(KEYFRAME_INTERVAL * 2) + 5).map(|i| {
+			let c = char::from(b'a' + (i % 26) as u8);
+
+			match i % 50 {
+				49 => grid.fill_rect(Rect::new(0, 0, 8, 4), GridCell::new(c)),
+				_ => grid.set(i % 8, (i / 8) % 4, GridCell::new(c)),
+			}
+
+			grid.clone()
+		}).collect();
+
+		let mut sequence = GridSequence::new(NonZeroUsize::new(8).unwrap(), NonZeroUsize::new(4).unwrap(), FontSize::Pixel(16.0));
+		for (i, grid) in grids.iter().enumerate() {
+			sequence.append(Frame::variable(grid.clone(), NonZeroU8::new((i % 3) as u8 + 1).unwrap()));
+		}
+
+		assert_eq!(sequence.len(), grids.len());
+		for (i, grid) in grids.iter().enumerate() {
+			let frame = sequence.get(i).unwrap();
+			assert!(frame.grid == *grid);
+			assert_eq!(frame.frame_hold.get(), (i % 3) as u8 + 1);
+		}
+
+		assert!(sequence.get(grids.len()).is_none());
+		assert!(sequence.iter().map(|frame| frame.grid).eq(grids.iter().cloned()));
+
+		let first = Grid::new(8, 4);
+		sequence.prepend(Frame::single(first.clone()));
+
+		assert!(sequence.pop().is_some_and(|frame| frame.grid == first));
+		for grid in &grids {
+			assert!(sequence.pop().is_some_and(|frame| frame.grid == *grid));
 		}
+
+		assert!(sequence.pop().is_none());
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn json_round_trip() {
+		let mut sequence = GridSequence::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(2).unwrap(), Px(16.0));
+		sequence.framerate = NonZeroU8::new(12).unwrap();
+		sequence.palette.colors[200] = image::Rgba([1, 2, 3, 4]);
+
+		let mut grid = Grid::new(4, 2);
+		grid.writer().write_str("e\u{301}漢\nab");
+		sequence.append(Frame::single(grid.clone()));
+		grid.set(0, 1, GridCell::new('c'));
+		sequence.append(Frame::variable(grid, NonZeroU8::new(3).unwrap()));
+
+		let json = serde_json::to_string(&sequence).unwrap();
+		assert!(json.contains("\"e\u{301}\""));
+		assert!(json.contains("\"漢\""));
+
+		let loaded: GridSequence = serde_json::from_str(&json).unwrap();
+		assert_eq!(loaded.framerate, sequence.framerate);
+		assert_eq!(loaded.palette, sequence.palette);
+		assert_eq!(loaded.get_dimensions(), sequence.get_dimensions());
+		assert_eq!(loaded.glyph_set(), sequence.glyph_set());
+		assert!(loaded.iter().map(|frame| (frame.grid, frame.frame_hold))
+			.eq(sequence.iter().map(|frame| (frame.grid, frame.frame_hold))));
+
+		// the second frame is stored as a patch again, rather than the full grid it was written as
+		assert!(matches!(loaded.frames[1].grid, Stored::Patch(ref patch) if patch.len() == 1));
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn json_mismatched_frame() {
+		let mut sequence = GridSequence::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(2).unwrap(), Px(16.0));
+		sequence.append(Frame::single(Grid::new(4, 2)));
+
+		let mut json = serde_json::to_value(&sequence).unwrap();
+		json["width"] = 5.into();
+
+		assert!(serde_json::from_value::<GridSequence>(json).is_err());
+	}
+
+	#[test]
+	fn seek() {
+		let mut sequence = GridSequence::new(NonZeroUsize::MIN, NonZeroUsize::MIN, Px(16.0));
+		sequence.framerate = NonZeroU8::new(30).unwrap();
+
+		for frame_hold in [1, 2, 1, 7, 3] {
+			sequence.append(Frame::variable(Grid::new(1, 1), NonZeroU8::new(frame_hold).unwrap()));
+		}
+
+		// a thirtieth of a second isn't a whole number of nanoseconds
+		assert_eq!(sequence.start_time(1), Some(Duration::from_nanos(33_333_334)));
+
+		for i in 0..sequence.len() {
+			let start = sequence.start_time(i).unwrap();
+			assert_eq!(sequence.index_at(start), Some(i));
+
+			if i > 0 {
+				assert_eq!(sequence.index_at(start - Duration::from_nanos(1)), Some(i - 1));
+			}
+		}
+
+		assert_eq!(sequence.index_at(Duration::from_millis(466)), Some(4));
+		assert_eq!(sequence.index_at(Duration::from_millis(467)), None);
+		assert_eq!(sequence.start_time(sequence.len()), None);
 	}
 }
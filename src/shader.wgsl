@@ -1,11 +1,25 @@
 @group(0) @binding(0) var<storage, read> idx_grid: array<u32>;
-@group(0) @binding(1) var<storage, read> atlas: array<u32>;
-@group(0) @binding(2) var output_img: texture_storage_2d<rgba8uint, write>;
 @group(0) @binding(3) var<uniform> grid_width: u32;
 @group(0) @binding(4) var<uniform> grid_height: u32;
 @group(0) @binding(5) var<uniform> img_width: u32;
 @group(0) @binding(6) var<uniform> img_height: u32;
 @group(0) @binding(7) var<storage, read> color_grid: array<u32>;
+@group(0) @binding(8) var<uniform> cursor: Cursor;
+@group(1) @binding(0) var output_img: texture_storage_2d<rgba8uint, write>;
+@group(2) @binding(0) var<storage, read> atlas: array<u32>;
+
+// the cells the cursor covers and how it's drawn, `columns` is 0 when it isn't
+struct Cursor {
+	x: u32,
+	y: u32,
+	columns: u32,
+	shape: u32,
+	// packed like `color_grid`, only used if `has_color` is set
+	color: u32,
+	has_color: u32,
+	thickness: u32,
+	_pad: u32,
+}
 
 fn grid_idx(img_pos: vec2<u32>) -> u32 {
 	let gx = (img_pos.x * grid_width) / img_width;
@@ -36,12 +50,63 @@ fn atlas_val_at(aidx: u32, rg_pos: vec2<u32>, glyph_size: vec2<u32>) -> u32 {
 	return unpack4xU8(atlas[qidx])[n_byte];
 }
 
-// lerp between `a` and `b` from `t=0` to `t=255`
+// lerp between `a` and `b` from `t=0` to `t=255`, weighting each color by its alpha
+// so that a transparent color doesn't darken the other, for opaque colors this is a plain lerp
 fn qlerp(a: vec4<u32>, b: vec4<u32>, t: u32) -> vec4<u32> {
-	let a1 = a * t;
-	let b1 = b * (255 - t);
+	let wa = a.a * t;
+	let wb = b.a * (255 - t);
+	let w = wa + wb;
+
+	if (w == 0) {
+		return vec4(0u);
+	}
+
+	return vec4((a.rgb * wa + b.rgb * wb) / w, w / 255);
+}
+
+const BLOCK: u32 = 0u;
+const HOLLOW_BLOCK: u32 = 1u;
+const BAR: u32 = 2u;
+
+// the pixel at `rg_pos` of cell `grid_pos`, with the cursor drawn over it if it's there
+fn draw_cursor(
+	grid_pos: vec2<u32>,
+	rg_pos: vec2<u32>,
+	glyph_size: vec2<u32>,
+	cov: u32,
+	fg_col: vec4<u32>,
+	bg_col: vec4<u32>
+) -> vec4<u32> {
+	let covered = grid_pos.y == cursor.y && grid_pos.x >= cursor.x && grid_pos.x < cursor.x + cursor.columns;
+	if (!covered) {
+		return qlerp(fg_col, bg_col, cov);
+	}
+
+	let color = select(fg_col, unpack4xU8(cursor.color), cursor.has_color != 0u);
+
+	// blocks draw the text in the cell's background, over the cursor
+	if (cursor.shape == BLOCK) {
+		return qlerp(bg_col, color, cov);
+	}
+
+	let pos = vec2(((grid_pos.x - cursor.x) * glyph_size.x) + rg_pos.x, rg_pos.y);
+	let size = vec2(cursor.columns * glyph_size.x, glyph_size.y);
+	let t = cursor.thickness;
+
+	var edge: bool;
+	switch cursor.shape {
+		case HOLLOW_BLOCK: {
+			edge = pos.x < t || pos.y < t || pos.x + t >= size.x || pos.y + t >= size.y;
+		}
+		case BAR: {
+			edge = pos.x < t;
+		}
+		default: {
+			edge = pos.y + t >= size.y;
+		}
+	}
 
-	return (a1 + b1) / 255;
+	return select(qlerp(fg_col, bg_col, cov), color, edge);
 }
 
 @compute @workgroup_size(16, 16, 1)
@@ -64,5 +129,6 @@ fn sample_atlas(@builtin(global_invocation_id) global_id: vec3<u32>) {
 	let cols = sample_colors(gidx);
 	let fg_col = unpack4xU8(cols[0]);
 	let bg_col = unpack4xU8(cols[1]);
-	textureStore(output_img, img_pos, qlerp(fg_col, bg_col, cov));
+	let grid_pos = vec2(gidx % grid_width, gidx / grid_width);
+	textureStore(output_img, img_pos, draw_cursor(grid_pos, rg_pos, glyph_size, cov, fg_col, bg_col));
 }
@@ -0,0 +1,51 @@
+use ttf_parser::gsub::{LigatureSubstitution, SubstitutionSubtable};
+use ttf_parser::{Face, GlyphId, Tag};
+
+// the features that combine a cluster's glyphs, such as precomposed accents and emoji sequences
+const FEATURES: [&[u8; 4]; 4] = [b"ccmp", b"rlig", b"liga", b"clig"];
+
+// replaces runs of `glyphs` with the font's ligatures for them,
+// this is nowhere near a full shaper but it's enough for a single cluster
+pub(crate) fn ligate(face: &Face, glyphs: &mut Vec<GlyphId>) {
+	let Some(gsub) = face.tables().gsub else {
+		return;
+	};
+
+	let mut lookups: Vec<u16> = FEATURES.iter()
+		.filter_map(|tag| gsub.features.find(Tag::from_bytes(tag)))
+		.flat_map(|feature| feature.lookup_indices)
+		.collect();
+
+	// lookups are applied in the order the font lists them, not by feature
+	lookups.sort_unstable();
+	lookups.dedup();
+
+	for lookup in lookups.into_iter().filter_map(|i| gsub.lookups.get(i)) {
+		for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+			if let SubstitutionSubtable::Ligature(ligatures) = subtable {
+				apply(&ligatures, glyphs);
+			}
+		}
+	}
+}
+
+fn apply(ligatures: &LigatureSubstitution, glyphs: &mut Vec<GlyphId>) {
+	let mut i = 0;
+
+	while i < glyphs.len() {
+		let found = ligatures.coverage.get(glyphs[i])
+			.and_then(|set| ligatures.ligature_sets.get(set))
+			.and_then(|set| set.into_iter().find(|ligature| {
+				let rest = &glyphs[i + 1..];
+				rest.len() >= ligature.components.len() as usize
+					&& ligature.components.into_iter().zip(rest).all(|(a, &b)| a == b)
+			}));
+
+		if let Some(ligature) = found {
+			let end = i + 1 + ligature.components.len() as usize;
+			glyphs.splice(i..end, [ligature.glyph]);
+		}
+
+		i += 1;
+	}
+}
@@ -0,0 +1,102 @@
+use crate::error::Error;
+use crate::gpu_render::{MapSlot, WgpuRenderer};
+use crate::render::RenderedFrame;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::sync::mpsc;
+
+/// the frames rendered by a [`WgpuRenderer`] as an async stream
+///
+/// buffer mapping is awaited rather than blocked on, the device is polled from a background thread
+/// which wakes the stream once a frame is ready
+pub struct FrameStream {
+	renderer: WgpuRenderer,
+	poller: mpsc::Sender<(wgpu::SubmissionIndex, MapSlot)>,
+}
+
+impl WgpuRenderer {
+	pub fn into_stream(self) -> FrameStream {
+		let device = self.device.clone();
+		let (poller, submissions) = mpsc::channel::<(wgpu::SubmissionIndex, MapSlot)>();
+
+		// exits once the stream is dropped
+		std::thread::spawn(move || {
+			for (submission, mapped) in submissions {
+				if device.poll(wgpu::PollType::WaitForSubmissionIndex(submission)).is_err() {
+					// the callback may never run now, so don't leave the stream hanging
+					let mut state = mapped.lock().unwrap();
+					state.result.get_or_insert(Err(wgpu::BufferAsyncError));
+
+					if let Some(waker) = state.waker.take() {
+						waker.wake();
+					}
+				}
+			}
+		});
+
+		FrameStream {
+			renderer: self,
+			poller,
+		}
+	}
+}
+
+impl FrameStream {
+	#[inline]
+	pub fn renderer(&self) -> &WgpuRenderer {
+		&self.renderer
+	}
+
+	/// the error that ended the stream early, if any
+	#[inline]
+	pub fn take_error(&mut self) -> Option<Error> {
+		self.renderer.error.take()
+	}
+}
+
+impl Stream for FrameStream {
+	type Item = RenderedFrame;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+
+		// once there's an error, nothing more is queued, but the frames already in flight are still read back
+		if this.renderer.error.is_none()
+			&& let Err(e) = this.renderer.submit_pending()
+		{
+			this.renderer.error = Some(e);
+		}
+
+		let Some(in_flight) = this.renderer.in_flight.front_mut() else {
+			return Poll::Ready(None);
+		};
+
+		{
+			let mut state = in_flight.mapped.lock().unwrap();
+			if state.result.is_none() {
+				state.waker = Some(cx.waker().clone());
+				drop(state);
+
+				if !in_flight.poll_requested {
+					in_flight.poll_requested = true;
+					let _ = this.poller.send((
+						in_flight.submission.clone(),
+						MapSlot::clone(&in_flight.mapped),
+					));
+				}
+
+				return Poll::Pending;
+			}
+		}
+
+		match this.renderer.finish_front() {
+			Ok(rendered) => Poll::Ready(rendered),
+			Err(e) => {
+				this.renderer.error.get_or_insert(e);
+				this.renderer.abandon_in_flight();
+				Poll::Ready(None)
+			}
+		}
+	}
+}
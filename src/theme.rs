@@ -0,0 +1,511 @@
+use crate::color::Palette;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+
+// the names most formats give the ANSI colors, in order
+const ANSI_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+// `#rgb`, `#rrggbb`, `0xrrggbb` or X11's `rgb:r/g/b` with 1 to 4 hex digits per channel
+fn parse_hex(value: &str) -> Option<image::Rgba<u8>> {
+	let value = value.trim();
+
+	if let Some(channels) = value.strip_prefix("rgb:") {
+		let mut rgb = [0; 3];
+		let mut channels = channels.split('/');
+
+		for channel in &mut rgb {
+			let digits = channels.next().filter(|c| (1..=4).contains(&c.len()))?;
+			let max = (1u32 << (4 * digits.len())) - 1;
+			*channel = (u32::from_str_radix(digits, 16).ok()? * u8::MAX as u32 / max) as u8;
+		}
+
+		return channels.next().is_none().then_some(image::Rgba([rgb[0], rgb[1], rgb[2], u8::MAX]));
+	}
+
+	let digits = value.strip_prefix('#')
+		.or_else(|| value.strip_prefix("0x"))?;
+	let n = u32::from_str_radix(digits, 16).ok()?;
+
+	let [r, g, b] = match digits.len() {
+		3 => [n >> 8, n >> 4, n].map(|c| (c & 0xf) as u8 * 0x11),
+		6 => [n >> 16, n >> 8, n].map(|c| (c & 0xff) as u8),
+		_ => return None
+	};
+
+	Some(image::Rgba([r, g, b, u8::MAX]))
+}
+
+#[inline]
+fn color(key: &str, value: &str) -> Result<image::Rgba<u8>> {
+	parse_hex(value).ok_or_else(|| Error::InvalidColor {
+		key: key.to_owned(),
+		value: value.to_owned()
+	})
+}
+
+// a plist dict is a flat list of keys, each followed by its value
+fn plist_dict<'a, 'input>(dict: roxmltree::Node<'a, 'input>) -> HashMap<String, roxmltree::Node<'a, 'input>> {
+	let mut children = dict.children().filter(roxmltree::Node::is_element);
+	let mut entries = HashMap::new();
+
+	while let (Some(key), Some(value)) = (children.next(), children.next()) {
+		if let Some(key) = key.text() {
+			entries.insert(key.to_owned(), value);
+		}
+	}
+
+	entries
+}
+
+// every loader starts from the default palette and replaces whatever colors the theme sets,
+// colors past the 16 ANSI ones keep the standard cube and grayscale ramp
+// display P3 shares sRGB's transfer function but has wider primaries,
+// colors outside of sRGB are clamped to its gamut
+fn p3_to_srgb(rgb: [f32; 3]) -> [f32; 3] {
+	const P3_TO_SRGB: [[f32; 3]; 3] = [
+		[1.224_940_2, -0.224_940_18, 0.0],
+		[-0.042_056_955, 1.042_056_9, 0.0],
+		[-0.019_637_555, -0.078_636_05, 1.098_273_6]
+	];
+
+	let linear = rgb.map(|c| match c {
+		..=0.04045 => c / 12.92,
+		_ => ((c + 0.055) / 1.055).powf(2.4)
+	});
+
+	P3_TO_SRGB.map(|row| {
+		let c = (row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]).clamp(0.0, 1.0);
+
+		match c {
+			..=0.003_130_8 => c * 12.92,
+			_ => (1.055 * c.powf(1.0 / 2.4)) - 0.055
+		}
+	})
+}
+
+impl Palette {
+	/// an Alacritty `.toml` theme
+	pub fn from_alacritty(theme: &str) -> Result<Self> {
+		let theme: toml::Table = theme.parse()?;
+		let mut palette = Self::default();
+
+		let Some(colors) = theme.get("colors").and_then(toml::Value::as_table) else {
+			return Ok(palette);
+		};
+
+		let get = |section: &str, key: &str| colors.get(section)
+			.and_then(|section| section.get(key))
+			.and_then(toml::Value::as_str);
+
+		if let Some(value) = get("primary", "foreground") {
+			palette.foreground = color("primary.foreground", value)?;
+		}
+
+		if let Some(value) = get("primary", "background") {
+			palette.background = color("primary.background", value)?;
+		}
+
+		// alacritty also accepts `CellForeground` and `CellBackground` here, which have no fixed color
+		palette.cursor = get("cursor", "cursor").and_then(parse_hex);
+		palette.selection = get("selection", "background").and_then(parse_hex);
+
+		for (offset, section) in [(0, "normal"), (8, "bright")] {
+			for (i, name) in ANSI_NAMES.iter().enumerate() {
+				if let Some(value) = get(section, name) {
+					palette.colors[offset + i] = color(&format!("{section}.{name}"), value)?;
+				}
+			}
+		}
+
+		Ok(palette)
+	}
+
+	/// a Kitty `.conf` theme
+	pub fn from_kitty(theme: &str) -> Result<Self> {
+		let mut palette = Self::default();
+
+		for line in theme.lines().map(str::trim) {
+			if line.starts_with('#') {
+				continue;
+			}
+
+			let Some((key, value)) = line.split_once(char::is_whitespace) else {
+				continue;
+			};
+			let value = value.trim();
+
+			match key {
+				"foreground" => palette.foreground = color(key, value)?,
+				"background" => palette.background = color(key, value)?,
+				// `none` draws the cursor by swapping its cell's colors
+				"cursor" => palette.cursor = parse_hex(value),
+				"selection_background" => palette.selection = parse_hex(value),
+				_ => {
+					if let Some(i) = key.strip_prefix("color")
+						.and_then(|i| i.parse::<usize>().ok())
+						.filter(|&i| i < 16)
+					{
+						palette.colors[i] = color(key, value)?;
+					}
+				}
+			}
+		}
+
+		Ok(palette)
+	}
+
+	/// an iTerm2 `.itermcolors` property list
+	pub fn from_iterm2(theme: &str) -> Result<Self> {
+		// every plist starts with a doctype
+		let options = roxmltree::ParsingOptions {
+			allow_dtd: true,
+			..Default::default()
+		};
+		let doc = roxmltree::Document::parse_with_options(theme, options)?;
+		let mut palette = Self::default();
+
+		let Some(root) = doc.root_element()
+			.children()
+			.find(|node| node.has_tag_name("dict"))
+		else {
+			return Ok(palette);
+		};
+
+		for (key, value) in plist_dict(root) {
+			let channels = plist_dict(value);
+			let channel = |name: &str| -> Result<f32> {
+				channels.get(&format!("{name} Component"))
+					.and_then(|node| node.text())
+					.and_then(|text| text.trim().parse::<f32>().ok())
+					.ok_or_else(|| Error::InvalidColor {
+						key: key.clone(),
+						value: format!("missing or invalid {name} component")
+					})
+			};
+
+			let slot = match key.as_str() {
+				"Foreground Color" => &mut palette.foreground,
+				"Background Color" => &mut palette.background,
+				"Cursor Color" => palette.cursor.insert(palette.foreground),
+				"Selection Color" => palette.selection.insert(palette.background),
+				_ => match key.strip_prefix("Ansi ")
+					.and_then(|key| key.strip_suffix(" Color"))
+					.and_then(|i| i.parse::<usize>().ok())
+					.filter(|&i| i < 16)
+				{
+					Some(i) => &mut palette.colors[i],
+					None => continue
+				}
+			};
+
+			let mut rgb = [channel("Red")?, channel("Green")?, channel("Blue")?];
+
+			// themes without a color space are sRGB, as are `Calibrated` ones near enough
+			if channels.get("Color Space").and_then(|node| node.text()) == Some("P3") {
+				rgb = p3_to_srgb(rgb);
+			}
+
+			let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8);
+			*slot = image::Rgba([r, g, b, u8::MAX]);
+		}
+
+		Ok(palette)
+	}
+
+	/// a Windows Terminal color scheme, the object found in the `schemes` list of its settings
+	pub fn from_windows_terminal(theme: &str) -> Result<Self> {
+		let theme: serde_json::Value = serde_json::from_str(theme)?;
+		let mut palette = Self::default();
+
+		let get = |key: &str| theme.get(key).and_then(serde_json::Value::as_str);
+
+		if let Some(value) = get("foreground") {
+			palette.foreground = color("foreground", value)?;
+		}
+
+		if let Some(value) = get("background") {
+			palette.background = color("background", value)?;
+		}
+
+		palette.cursor = get("cursorColor").and_then(parse_hex);
+		palette.selection = get("selectionBackground").and_then(parse_hex);
+
+		// windows terminal calls magenta purple
+		let names = ANSI_NAMES.map(|name| if name == "magenta" { "purple" } else { name });
+
+		for (i, name) in names.iter().enumerate() {
+			if let Some(value) = get(name) {
+				palette.colors[i] = color(name, value)?;
+			}
+
+			let bright = format!("bright{}{}", name[..1].to_uppercase(), &name[1..]);
+			if let Some(value) = get(&bright) {
+				palette.colors[i + 8] = color(&bright, value)?;
+			}
+		}
+
+		Ok(palette)
+	}
+
+	/// an `.Xresources` theme, `#define`d names are substituted but other preprocessor directives are ignored
+	pub fn from_xresources(theme: &str) -> Result<Self> {
+		let mut palette = Self::default();
+		let mut defines = HashMap::new();
+
+		for line in theme.lines().map(str::trim) {
+			if let Some(define) = line.strip_prefix("#define") {
+				if let Some((name, value)) = define.trim().split_once(char::is_whitespace) {
+					defines.insert(name, value.trim());
+				}
+
+				continue;
+			}
+
+			if line.starts_with('!') || line.starts_with('#') {
+				continue;
+			}
+
+			let Some((key, value)) = line.split_once(':') else {
+				continue;
+			};
+
+			// `*.color0`, `*color0` and `URxvt.color0` all name the same resource
+			let key = key.trim().rsplit(['.', '*']).next().unwrap_or_default();
+			let value = value.trim();
+			let value = defines.get(value).copied().unwrap_or(value);
+
+			match key {
+				"foreground" => palette.foreground = color(key, value)?,
+				"background" => palette.background = color(key, value)?,
+				"cursorColor" => palette.cursor = Some(color(key, value)?),
+				"highlightColor" => palette.selection = Some(color(key, value)?),
+				_ => {
+					if let Some(i) = key.strip_prefix("color")
+						.and_then(|i| i.parse::<usize>().ok())
+						.filter(|&i| i < 16)
+					{
+						palette.colors[i] = color(key, value)?;
+					}
+				}
+			}
+		}
+
+		Ok(palette)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[inline]
+	const fn rgb(r: u8, g: u8, b: u8) -> image::Rgba<u8> {
+		image::Rgba([r, g, b, u8::MAX])
+	}
+
+	#[test]
+	fn alacritty() {
+		let palette = Palette::from_alacritty(r##"
+			[colors.primary]
+			foreground = "#c5c8c6"
+			background = "0x1d1f21"
+
+			[colors.cursor]
+			cursor = "CellForeground"
+
+			[colors.selection]
+			background = "#fa0"
+
+			[colors.normal]
+			red = "rgb:cc/66/6"
+
+			[colors.bright]
+			blue = "rgb:8/bebe/fff"
+		"##).unwrap();
+
+		assert_eq!(palette.foreground, rgb(0xc5, 0xc8, 0xc6));
+		assert_eq!(palette.background, rgb(0x1d, 0x1f, 0x21));
+		assert_eq!(palette.cursor, None);
+		assert_eq!(palette.selection, Some(rgb(0xff, 0xaa, 0x00)));
+		assert_eq!(palette.colors[1], rgb(0xcc, 0x66, 0x66));
+		assert_eq!(palette.colors[12], rgb(0x88, 0xbe, 0xff));
+		assert_eq!(palette.colors[2..12], Palette::default().colors[2..12]);
+		assert_eq!(palette.colors[16..], Palette::default().colors[16..]);
+
+		assert!(matches!(
+			Palette::from_alacritty("[colors.normal]\nred = \"#12345\""),
+			Err(Error::InvalidColor { key, .. }) if key == "normal.red"
+		));
+	}
+
+	#[test]
+	fn kitty() {
+		let palette = Palette::from_kitty("
+			# a comment
+			foreground #dddddd
+			background  #000
+			cursor none
+			selection_background #444444
+			color0 #101010
+			color15   #fefefe
+			color16 #123456
+		").unwrap();
+
+		assert_eq!(palette.foreground, rgb(0xdd, 0xdd, 0xdd));
+		assert_eq!(palette.background, rgb(0, 0, 0));
+		assert_eq!(palette.cursor, None);
+		assert_eq!(palette.selection, Some(rgb(0x44, 0x44, 0x44)));
+		assert_eq!(palette.colors[0], rgb(0x10, 0x10, 0x10));
+		assert_eq!(palette.colors[15], rgb(0xfe, 0xfe, 0xfe));
+		assert_eq!(palette.colors[16], Palette::default().colors[16]);
+	}
+
+	#[test]
+	fn iterm2() {
+		let palette = Palette::from_iterm2(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Ansi 3 Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.0</real>
+		<key>Green Component</key>
+		<real>0.5</real>
+		<key>Red Component</key>
+		<real>1</real>
+	</dict>
+	<key>Background Color</key>
+	<dict>
+		<key>Alpha Component</key>
+		<real>1</real>
+		<key>Blue Component</key>
+		<real>0.2</real>
+		<key>Green Component</key>
+		<real>0.1</real>
+		<key>Red Component</key>
+		<real>0.0</real>
+	</dict>
+	<key>Cursor Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>1.5</real>
+		<key>Green Component</key>
+		<real>1</real>
+		<key>Red Component</key>
+		<real>1</real>
+	</dict>
+	<key>Ansi 16 Color</key>
+	<dict/>
+</dict>
+</plist>"#).unwrap();
+
+		assert_eq!(palette.colors[3], rgb(0xff, 0x80, 0x00));
+		assert_eq!(palette.background, rgb(0x00, 0x1a, 0x33));
+		assert_eq!(palette.cursor, Some(rgb(0xff, 0xff, 0xff)));
+		assert_eq!(palette.selection, None);
+		assert_eq!(palette.colors[16], Palette::default().colors[16]);
+
+		assert!(matches!(
+			Palette::from_iterm2("<plist><dict><key>Foreground Color</key><dict/></dict></plist>"),
+			Err(Error::InvalidColor { key, .. }) if key == "Foreground Color"
+		));
+	}
+
+	#[test]
+	fn windows_terminal() {
+		let palette = Palette::from_windows_terminal(r##"{
+			"name": "Campbell",
+			"foreground": "#CCCCCC",
+			"background": "#0C0C0C",
+			"cursorColor": "#FFFFFF",
+			"selectionBackground": "#FFFFFF",
+			"purple": "#881798",
+			"brightPurple": "#B4009E",
+			"brightBlack": "#767676"
+		}"##).unwrap();
+
+		assert_eq!(palette.foreground, rgb(0xcc, 0xcc, 0xcc));
+		assert_eq!(palette.background, rgb(0x0c, 0x0c, 0x0c));
+		assert_eq!(palette.cursor, Some(rgb(0xff, 0xff, 0xff)));
+		assert_eq!(palette.selection, Some(rgb(0xff, 0xff, 0xff)));
+		assert_eq!(palette.colors[5], rgb(0x88, 0x17, 0x98));
+		assert_eq!(palette.colors[13], rgb(0xb4, 0x00, 0x9e));
+		assert_eq!(palette.colors[8], rgb(0x76, 0x76, 0x76));
+		assert_eq!(palette.colors[0], Palette::default().colors[0]);
+	}
+
+	#[test]
+	fn xresources() {
+		let palette = Palette::from_xresources("
+			! a comment
+			#define base00 #1d1f21
+			#define red    rgb:cc/66/66
+			#include \"other\"
+
+			*.foreground:   #c5c8c6
+			*background:    base00
+			URxvt.cursorColor: #fff
+			*.color1: red
+			*color9:  red
+			URxvt*color15: #ffffff
+		").unwrap();
+
+		assert_eq!(palette.foreground, rgb(0xc5, 0xc8, 0xc6));
+		assert_eq!(palette.background, rgb(0x1d, 0x1f, 0x21));
+		assert_eq!(palette.cursor, Some(rgb(0xff, 0xff, 0xff)));
+		assert_eq!(palette.selection, None);
+		assert_eq!(palette.colors[1], rgb(0xcc, 0x66, 0x66));
+		assert_eq!(palette.colors[9], rgb(0xcc, 0x66, 0x66));
+		assert_eq!(palette.colors[15], rgb(0xff, 0xff, 0xff));
+		assert_eq!(palette.colors[2], Palette::default().colors[2]);
+	}
+
+	#[test]
+	fn iterm2_p3() {
+		let palette = Palette::from_iterm2(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Foreground Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.4</real>
+		<key>Color Space</key>
+		<string>P3</string>
+		<key>Green Component</key>
+		<real>0.6</real>
+		<key>Red Component</key>
+		<real>0.2</real>
+	</dict>
+	<key>Background Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.4</real>
+		<key>Color Space</key>
+		<string>sRGB</string>
+		<key>Green Component</key>
+		<real>0.6</real>
+		<key>Red Component</key>
+		<real>0.2</real>
+	</dict>
+	<key>Ansi 0 Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.6</real>
+		<key>Color Space</key>
+		<string>P3</string>
+		<key>Green Component</key>
+		<real>0.6</real>
+		<key>Red Component</key>
+		<real>0.6</real>
+	</dict>
+</dict>
+</plist>"#).unwrap();
+
+		// the red is outside of sRGB, grays are the same in both
+		assert_eq!(palette.foreground, rgb(0, 156, 97));
+		assert_eq!(palette.background, rgb(51, 153, 102));
+		assert_eq!(palette.colors[0], rgb(153, 153, 153));
+	}
+}
@@ -4,11 +4,67 @@ use gstreamer_video as gst_vid;
 use gstreamer_pbutils as gst_pbutils;
 use gst::prelude::{Cast, GstBinExtManual, ElementExt, ObjectExt};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use crate::error::{Error, Result};
 use crate::render::VideoSrc;
 
 pub struct DvdEncoder<S> {
-	src: S
+	src: S,
+	format: OutputFormat
+}
+
+/// the codec and container a video is saved as
+///
+/// frames keep whatever transparency their colors have (see [`Palette::background`](crate::color::Palette::background)),
+/// only formats with an alpha channel carry it into the video
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+	/// VP9 in a Matroska file, without transparency
+	#[default]
+	Vp9Mkv,
+	/// ProRes 4444 in a QuickTime file, with an alpha channel that editors can composite with,
+	/// this needs `avenc_prores_ks` from gst-libav
+	ProRes4444Mov
+}
+
+impl OutputFormat {
+	/// whether the video keeps the frames' alpha channel
+	#[inline]
+	pub fn has_alpha(self) -> bool {
+		match self {
+			Self::Vp9Mkv => false,
+			Self::ProRes4444Mov => true
+		}
+	}
+
+	// the elements between the raw frames and the file, in the order they're linked
+	fn elements(self) -> Result<Vec<gst::Element>> {
+		match self {
+			Self::Vp9Mkv => {
+				let encodebin = gst::ElementFactory::make("encodebin").build()?;
+				encodebin.set_property("profile", gst_pbutils::EncodingContainerProfile::builder(
+					&gst::Caps::builder("video/x-matroska").build()
+				).add_profile(
+					gst_pbutils::EncodingVideoProfile::builder(&gst::Caps::builder("video/x-vp9").build()).build()
+				).build());
+
+				Ok(vec![encodebin])
+			},
+			Self::ProRes4444Mov => {
+				let convert = gst::ElementFactory::make("videoconvert").build()?;
+				// the only format with alpha that prores_ks takes, picked explicitly so it isn't converted away
+				let filter = gst::ElementFactory::make("capsfilter")
+					.property("caps", gst_vid::VideoCapsBuilder::new().format(gst_vid::VideoFormat::A44410le).build())
+					.build()?;
+				let encoder = gst::ElementFactory::make("avenc_prores_ks")
+					.property_from_str("profile", "4444")
+					.build()?;
+				let mux = gst::ElementFactory::make("qtmux").build()?;
+
+				Ok(vec![convert, filter, encoder, mux])
+			}
+		}
+	}
 }
 
 #[derive(Clone)]
@@ -28,12 +84,21 @@ impl AsRef<[u8]> for ArcFrame {
 
 impl<S: VideoSrc> DvdEncoder<S> {
 	pub fn new(src: S) -> Self {
-		Self { src }
+		Self {
+			src,
+			format: OutputFormat::default()
+		}
+	}
+
+	/// the format `save_video_to` writes, VP9 in Matroska unless set
+	#[inline]
+	pub fn set_format(&mut self, format: OutputFormat) {
+		self.format = format;
 	}
 
 	// mostly stitched together example code from the gstreamer gitlab
-	pub fn save_video_to<P: AsRef<Path>>(self, path: P) {
-		gst::init().unwrap();
+	pub fn save_video_to<P: AsRef<Path>>(self, path: P) -> Result<()> {
+		gst::init()?;
 
 		let pipeline = gst::Pipeline::default();
 
@@ -41,31 +106,44 @@ impl<S: VideoSrc> DvdEncoder<S> {
 			gst_vid::VideoFormat::Rgba,
 			self.src.width(),
 			self.src.height()
-		).fps(gst::Fraction::new(self.src.framerate().get() as i32, 1)).build().unwrap();
+		).fps(gst::Fraction::new(self.src.framerate().get() as i32, 1)).build()?;
 
 		let appsrc = gst_app::AppSrc::builder()
-			.caps(&vid_info.to_caps().unwrap())
+			.caps(&vid_info.to_caps()?)
 			.format(gst::Format::Time)
 			.build();
 
-		let encodebin = gst::ElementFactory::make("encodebin").build().unwrap();
-		encodebin.set_property("profile", gst_pbutils::EncodingContainerProfile::builder(
-			&gst::Caps::builder("video/x-matroska").build()
-		).add_profile(
-			gst_pbutils::EncodingVideoProfile::builder(&gst::Caps::builder("video/x-vp9").build()).build()
-		).build());
 		let sink = gst::ElementFactory::make("filesink")
 			.property("location", path.as_ref())
-			.build().unwrap();
+			.build()?;
+
+		let mut elements = vec![appsrc.clone().upcast::<gst::Element>()];
+		elements.extend(self.format.elements()?);
+		elements.push(sink);
 
-		pipeline.add_many([appsrc.upcast_ref(), &encodebin, &sink]).unwrap();
-		gst::Element::link_many([appsrc.upcast_ref(), &encodebin, &sink]).unwrap();
+		pipeline.add_many(&elements)?;
+		gst::Element::link_many(&elements)?;
 
 		let frametime = gst::ClockTime::SECOND / self.src.framerate().get() as u64;
-		let mut frames_iter = self.src.flat_map(|frame| {
+		// errors raised inside the `need_data` callback, reported once the pipeline stops
+		let error = Arc::new(Mutex::new(None));
+		let src_error = Arc::clone(&error);
+
+		let mut src = self.src;
+		let mut frames_iter = std::iter::from_fn(move || {
+			let frame = src.next();
+			if frame.is_none()
+				&& let Some(e) = src.take_error()
+			{
+				src_error.lock().unwrap().get_or_insert(e);
+			}
+
+			frame
+		}).flat_map(|frame| {
 			let img = ArcFrame::new(frame.img);
 			std::iter::repeat_n(img, frame.frame_hold.get() as usize)
 		});
+		let push_error = Arc::clone(&error);
 		let mut n: u64 = 0;
 		appsrc.set_callbacks(gst_app::AppSrcCallbacks::builder().need_data(move |appsrc, _| {
 			let Some(frame) = frames_iter.next() else {
@@ -75,25 +153,34 @@ impl<S: VideoSrc> DvdEncoder<S> {
 
 			let mut buffer = gst::Buffer::from_slice(ArcFrame::clone(&frame));
 			buffer.get_mut().unwrap().set_pts(frametime * n);
-			appsrc.push_buffer(buffer).unwrap();
+			if let Err(e) = appsrc.push_buffer(buffer) {
+				push_error.lock().unwrap().get_or_insert(Error::Flow(e));
+				let _ = appsrc.end_of_stream();
+				return;
+			}
 			n += 1;
 		}).build());
 
-		pipeline.set_state(gst::State::Playing).unwrap();
+		pipeline.set_state(gst::State::Playing)?;
 
-		let bus = pipeline.bus().unwrap();
+		let bus = pipeline.bus().ok_or(Error::NoBus)?;
 
 		for msg in bus.iter_timed(gst::ClockTime::NONE) {
 			match msg.view() {
 				gst::MessageView::Eos(..) => break,
 				gst::MessageView::Error(err) => {
-					pipeline.set_state(gst::State::Null).unwrap();
-					panic!("pipeline failed {err}");
+					pipeline.set_state(gst::State::Null)?;
+					return Err(Error::Glib(err.error()));
 				},
 				_ => ()
 			}
 		}
 
-		pipeline.set_state(gst::State::Null).unwrap();
+		pipeline.set_state(gst::State::Null)?;
+
+		match error.lock().unwrap().take() {
+			Some(e) => Err(e),
+			None => Ok(())
+		}
 	}
 }
@@ -0,0 +1,202 @@
+use core::fmt;
+use crate::cluster::Cluster;
+use crate::color::Color;
+use crate::grid::{Attributes, Grid, GridCell};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// writes text into a [`Grid`] at a cursor, the way a terminal would
+///
+/// text is written in the writer's current colors and attributes,
+/// `\n` moves to the start of the next line, `\r` to the start of the current one
+/// and `\t` to the next tab stop, other control characters are ignored,
+/// once the cursor moves past the bottom row the whole grid scrolls up
+pub struct Writer<'a> {
+	grid: &'a mut Grid,
+	x: usize,
+	y: usize,
+	fg_color: Color,
+	bg_color: Color,
+	attrs: Attributes,
+	tab_width: usize,
+	wrap: bool
+}
+
+impl<'a> Writer<'a> {
+	pub fn new(grid: &'a mut Grid) -> Self {
+		Self {
+			grid,
+			x: 0,
+			y: 0,
+			fg_color: Color::Default,
+			bg_color: Color::Default,
+			attrs: Attributes::NONE,
+			tab_width: 8,
+			wrap: true
+		}
+	}
+
+	/// the cursor's column and row
+	///
+	/// after writing into the last column the cursor sits one past it,
+	/// until the next character wraps onto the following line
+	#[inline]
+	pub fn cursor(&self) -> (usize, usize) {
+		(self.x, self.y)
+	}
+
+	/// moves the cursor, clamped to the grid
+	pub fn move_to(&mut self, x: usize, y: usize) {
+		self.x = x.min(self.grid.width().saturating_sub(1));
+		self.y = y.min(self.grid.height().saturating_sub(1));
+	}
+
+	#[inline]
+	pub fn set_fg_color(&mut self, fg_color: impl Into<Color>) {
+		self.fg_color = fg_color.into();
+	}
+
+	#[inline]
+	pub fn set_bg_color(&mut self, bg_color: impl Into<Color>) {
+		self.bg_color = bg_color.into();
+	}
+
+	#[inline]
+	pub fn set_attributes(&mut self, attrs: Attributes) {
+		self.attrs = attrs;
+	}
+
+	/// columns between tab stops, 8 by default
+	#[inline]
+	pub fn set_tab_width(&mut self, tab_width: usize) {
+		self.tab_width = tab_width.max(1);
+	}
+
+	/// whether text reaching the right edge continues on the next line, on by default
+	///
+	/// without wrapping, text past the right edge keeps overwriting the last column
+	#[inline]
+	pub fn set_wrap(&mut self, wrap: bool) {
+		self.wrap = wrap;
+	}
+
+	pub fn write_str(&mut self, s: &str) {
+		for cluster in s.graphemes(true) {
+			match cluster {
+				"\n" | "\r\n" => self.newline(),
+				"\r" => self.x = 0,
+				"\t" => {
+					let stop = ((self.x / self.tab_width) + 1) * self.tab_width;
+					self.x = stop.min(self.grid.width().saturating_sub(1)).max(self.x);
+				},
+				_ if cluster.starts_with(char::is_control) => (),
+				_ => {
+					let cluster = Cluster::new(cluster).expect("graphemes are never empty");
+					self.put(cluster);
+				}
+			}
+		}
+	}
+
+	fn put(&mut self, cluster: Cluster) {
+		let width = self.grid.width();
+		if width == 0 || self.grid.height() == 0 {
+			return;
+		}
+
+		let columns = cluster.width().min(width);
+		if self.x + columns > width {
+			match self.wrap {
+				true => self.newline(),
+				false => self.x = width - columns
+			}
+		}
+
+		let mut cell = GridCell::new_full_color(cluster, self.fg_color, self.bg_color);
+		cell.set_attributes(self.attrs);
+
+		self.grid.set(self.x, self.y, cell);
+		self.x += columns;
+	}
+
+	fn newline(&mut self) {
+		self.x = 0;
+		self.y += 1;
+
+		let height = self.grid.height();
+		if self.y >= height {
+			self.grid.scroll_up(0..height, 1);
+			self.y = height.saturating_sub(1);
+		}
+	}
+}
+
+impl fmt::Write for Writer<'_> {
+	#[inline]
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		Writer::write_str(self, s);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::grid::Grid;
+
+	fn text(grid: &Grid, y: usize) -> String {
+		grid.row(y).unwrap().iter().filter(|cell| !cell.is_continuation()).map(|cell| cell.character()).collect()
+	}
+
+	#[test]
+	fn wraps_at_last_column() {
+		let mut grid = Grid::new(4, 3);
+		let mut writer = grid.writer();
+		writer.write_str("abcd");
+		assert_eq!(writer.cursor(), (4, 0));
+
+		// a wide character that doesn't fit wraps whole
+		writer.write_str("ef漢g漢");
+		assert_eq!(writer.cursor(), (3, 2));
+
+		assert_eq!(text(&grid, 0), "abcd");
+		assert_eq!(text(&grid, 1), "ef漢");
+		assert_eq!(text(&grid, 2), "g漢 ");
+	}
+
+	#[test]
+	fn tab_stops() {
+		let mut grid = Grid::new(12, 2);
+		let mut writer = grid.writer();
+		writer.write_str("a\tb\n");
+		writer.set_tab_width(4);
+		writer.write_str("a\tb\tc\t\td");
+
+		assert_eq!(text(&grid, 0), "a       b   ");
+		assert_eq!(text(&grid, 1), "a   b   c  d");
+	}
+
+	#[test]
+	fn line_endings() {
+		let mut grid = Grid::new(4, 3);
+		let mut writer = grid.writer();
+		writer.write_str("abc\r\nde\nf\rg");
+		assert_eq!(writer.cursor(), (1, 2));
+
+		assert_eq!(text(&grid, 0), "abc ");
+		assert_eq!(text(&grid, 1), "de  ");
+		assert_eq!(text(&grid, 2), "g   ");
+	}
+
+	#[test]
+	fn scrolls_past_bottom() {
+		let mut grid = Grid::new(2, 2);
+		let mut writer = grid.writer();
+		writer.write_str("a\nb\nc");
+		assert_eq!(writer.cursor(), (1, 1));
+
+		writer.write_str("de");
+		assert_eq!(writer.cursor(), (1, 1));
+
+		assert_eq!(text(&grid, 0), "cd");
+		assert_eq!(text(&grid, 1), "e ");
+	}
+}